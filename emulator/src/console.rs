@@ -0,0 +1,45 @@
+//! The terminal of CP/M programs run with --cpm: standard input and output.
+//!
+//! Standard input is read a line at a time, so a program polling the console
+//! status only sees bytes of lines already read and never waits for a key.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+
+use i8080::Console;
+
+#[derive(Default)]
+pub struct StdConsole {
+  line: VecDeque<u8>,
+  ended: bool,
+}
+
+impl StdConsole {
+  pub fn new() -> StdConsole {
+    StdConsole::default()
+  }
+}
+
+impl Console for StdConsole {
+  fn read(&mut self) -> Option<u8> {
+    if self.line.is_empty() && !self.ended {
+      // show a prompt printed without a newline before waiting
+      let _ = io::stdout().flush();
+      let mut line = Vec::new();
+      match io::stdin().lock().read_until(b'\n', &mut line) {
+        Ok(0) | Err(_) => self.ended = true,
+        Ok(_) => self.line.extend(line),
+      }
+    }
+    self.line.pop_front()
+  }
+
+  fn ready(&mut self) -> bool {
+    !self.line.is_empty()
+  }
+
+  fn write(&mut self, byte: u8) {
+    // the Console trait has no way to report a closed terminal, the program keeps running
+    let _ = io::stdout().write_all(&[byte]);
+  }
+}
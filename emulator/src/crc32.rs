@@ -0,0 +1,19 @@
+//! CRC-32 as used by PNG, zip and ROM dumps
+
+pub fn crc32(data: &[u8]) -> u32 {
+  let mut crc = 0xffff_ffffu32;
+  for &byte in data {
+    crc ^= byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 { crc >> 1 ^ 0xedb8_8320 } else { crc >> 1 };
+    }
+  }
+  !crc
+}
+
+#[test]
+fn crc32_test() {
+  assert_eq!(crc32(b""), 0);
+  assert_eq!(crc32(b"IEND"), 0xae42_6082);
+  assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+}
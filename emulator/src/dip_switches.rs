@@ -0,0 +1,84 @@
+//! The DIP switches of the Space Invaders board, read through port 2.
+//!
+//! Settings are written `name=value`, on the command line or one per line in
+//! a file where `#` starts a comment:
+//!
+//! * `lives=3` to `lives=6`, ships at the start of a game
+//! * `bonus=1000` or `bonus=1500`, score for the extra ship
+//! * `coininfo=on` or `coininfo=off`, show the coin info on the demo screen
+
+use std::fmt;
+
+use lines::parse_lines;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DipSwitches {
+  pub lives: u8,
+  pub bonus_life: u16,
+  pub coin_info: bool,
+}
+
+impl DipSwitches {
+  /// The factory settings: 3 lives, bonus at 1500, coin info shown
+  pub fn new() -> DipSwitches {
+    DipSwitches { lives: 3, bonus_life: 1500, coin_info: true }
+  }
+
+  /// Applies one `name=value` setting
+  pub fn set(&mut self, setting: &str) -> Result<(), String> {
+    let (name, value) = match setting.find('=') {
+      Some(equals) => (setting[..equals].trim(), setting[equals + 1..].trim()),
+      None => return Err(format!("expected name=value, got \"{}\"", setting)),
+    };
+    match (name, value) {
+      ("lives", "3") | ("lives", "4") | ("lives", "5") | ("lives", "6") => self.lives = value.parse().unwrap(),
+      ("bonus", "1000") | ("bonus", "1500") => self.bonus_life = value.parse().unwrap(),
+      ("coininfo", "on") => self.coin_info = true,
+      ("coininfo", "off") => self.coin_info = false,
+      ("lives", _) | ("bonus", _) | ("coininfo", _) => return Err(format!("invalid value {} for {}", value, name)),
+      _ => return Err(format!("unknown DIP switch {}", name)),
+    }
+    Ok(())
+  }
+
+  /// Applies the settings of a file, see the module docs
+  pub fn set_from_file(&mut self, text: &str) -> Result<(), String> {
+    parse_lines(text, |line| self.set(line)).map(|_| ())
+  }
+
+  /// The switch bits of IN 2: lives - 3 in bits 0-1, bit 3 set for the bonus
+  /// at 1000, bit 7 set to hide the coin info
+  pub fn port2(&self) -> u8 {
+    (self.lives - 3) & 0x03 | if self.bonus_life == 1000 { 0x08 } else { 0x00 } | if self.coin_info { 0x00 } else { 0x80 }
+  }
+}
+
+impl Default for DipSwitches {
+  fn default() -> DipSwitches {
+    DipSwitches::new()
+  }
+}
+
+impl fmt::Display for DipSwitches {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "lives={} bonus={} coininfo={}", self.lives, self.bonus_life, if self.coin_info { "on" } else { "off" })
+  }
+}
+
+#[test]
+fn dip_switches_test() {
+  let mut dip_switches = DipSwitches::new();
+  assert_eq!(dip_switches.port2(), 0x00);
+
+  dip_switches.set("lives=5").unwrap();
+  dip_switches.set("bonus = 1000").unwrap();
+  assert_eq!(dip_switches.port2(), 0x0a);
+
+  dip_switches.set_from_file("# cabinet 2\ncoininfo=off\n\nlives=6  # generous\n").unwrap();
+  assert_eq!(dip_switches.port2(), 0x8b);
+  assert_eq!(dip_switches.to_string(), "lives=6 bonus=1000 coininfo=off");
+
+  assert_eq!(dip_switches.set("lives=9"), Err("invalid value 9 for lives".to_string()));
+  assert_eq!(dip_switches.set("speed=fast"), Err("unknown DIP switch speed".to_string()));
+  assert_eq!(dip_switches.set_from_file("lives=3\nbonus"), Err("line 2: expected name=value, got \"bonus\"".to_string()));
+}
@@ -0,0 +1,296 @@
+//! Player controls of the Space Invaders cabinet, and scripts that press them.
+//!
+//! Port 1 carries the coin slot, both start buttons and player 1's controls,
+//! port 2 player 2's controls and the tilt switch next to the DIP switches,
+//! port 0 a second copy of the player 1 controls the game does not read.
+//!
+//! Buttons are pressed by a script, a text file of lines like
+//! `frame 120: press COIN` where `#` starts a comment, or by keys typed on
+//! standard input. Buttons are COIN, START1, START2, FIRE, LEFT, RIGHT,
+//! FIRE2, LEFT2, RIGHT2 and TILT; see `Button::from_key` for the keys.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use lines::parse_lines;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+  Coin,
+  Start1,
+  Start2,
+  Fire1,
+  Left1,
+  Right1,
+  Fire2,
+  Left2,
+  Right2,
+  Tilt,
+}
+
+const BUTTONS: [(&str, Button); 10] = [
+  ("COIN", Button::Coin),
+  ("START1", Button::Start1),
+  ("START2", Button::Start2),
+  ("FIRE", Button::Fire1),
+  ("LEFT", Button::Left1),
+  ("RIGHT", Button::Right1),
+  ("FIRE2", Button::Fire2),
+  ("LEFT2", Button::Left2),
+  ("RIGHT2", Button::Right2),
+  ("TILT", Button::Tilt),
+];
+
+impl Button {
+  /// The button `key` presses: c coin, 1 and 2 start, a d and space for
+  /// player 1, j l and k for player 2, t tilt
+  pub fn from_key(key: u8) -> Option<Button> {
+    match key.to_ascii_lowercase() {
+      b'c' => Some(Button::Coin),
+      b'1' => Some(Button::Start1),
+      b'2' => Some(Button::Start2),
+      b' ' => Some(Button::Fire1),
+      b'a' => Some(Button::Left1),
+      b'd' => Some(Button::Right1),
+      b'k' => Some(Button::Fire2),
+      b'j' => Some(Button::Left2),
+      b'l' => Some(Button::Right2),
+      b't' => Some(Button::Tilt),
+      _ => None,
+    }
+  }
+}
+
+impl FromStr for Button {
+  type Err = String;
+
+  fn from_str(name: &str) -> Result<Button, String> {
+    BUTTONS.iter().find(|&&(button_name, _)| button_name.eq_ignore_ascii_case(name))
+      .map(|&(_, button)| button)
+      .ok_or_else(|| format!("unknown button {}", name))
+  }
+}
+
+impl fmt::Display for Button {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let &(name, _) = BUTTONS.iter().find(|&&(_, button)| button == *self).unwrap();
+    write!(f, "{}", name)
+  }
+}
+
+/// Which buttons are held down
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Controls {
+  pressed: u16,
+}
+
+impl Controls {
+  pub fn new() -> Controls {
+    Controls::default()
+  }
+
+  pub fn press(&mut self, button: Button) {
+    self.pressed |= 1 << button as u16;
+  }
+
+  pub fn release(&mut self, button: Button) {
+    self.pressed &= !(1 << button as u16);
+  }
+
+  pub fn is_pressed(&self, button: Button) -> bool {
+    self.pressed & 1 << button as u16 != 0
+  }
+
+  /// All buttons as bits, for save states
+  pub fn bits(&self) -> u16 {
+    self.pressed
+  }
+
+  pub fn from_bits(bits: u16) -> Controls {
+    Controls { pressed: bits }
+  }
+
+  fn bit(&self, button: Button, bit: u8) -> u8 {
+    (self.is_pressed(button) as u8) << bit
+  }
+
+  /// IN 0: bits 1-3 always set, player 1 fire, left and right in bits 4-6
+  pub fn port0(&self) -> u8 {
+    0x0e | self.bit(Button::Fire1, 4) | self.bit(Button::Left1, 5) | self.bit(Button::Right1, 6)
+  }
+
+  /// IN 1: coin, 2P start, 1P start, bit 3 always set, then player 1 fire, left and right
+  pub fn port1(&self) -> u8 {
+    self.bit(Button::Coin, 0) | self.bit(Button::Start2, 1) | self.bit(Button::Start1, 2) | 0x08 |
+      self.bit(Button::Fire1, 4) | self.bit(Button::Left1, 5) | self.bit(Button::Right1, 6)
+  }
+
+  /// IN 2 without the DIP switches: tilt, then player 2 fire, left and right
+  pub fn port2(&self) -> u8 {
+    self.bit(Button::Tilt, 2) | self.bit(Button::Fire2, 4) | self.bit(Button::Left2, 5) | self.bit(Button::Right2, 6)
+  }
+}
+
+/// One line of a script
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScriptEvent {
+  pub frame: u64,
+  pub button: Button,
+  pub pressed: bool,
+}
+
+/// Button presses and releases by frame number, see the module docs
+#[derive(Debug, Default, PartialEq)]
+pub struct Script {
+  events: Vec<ScriptEvent>,
+}
+
+impl Script {
+  /// Parses a script, see the module docs
+  pub fn parse(text: &str) -> Result<Script, String> {
+    let mut events = parse_lines(text, parse_line)?;
+    // stable, so events of the same frame keep their order
+    events.sort_by_key(|event| event.frame);
+    Ok(Script { events })
+  }
+
+  /// Applies the events of `frame` to `controls`
+  pub fn apply(&self, frame: u64, controls: &mut Controls) {
+    for event in self.events.iter().filter(|event| event.frame == frame) {
+      if event.pressed { controls.press(event.button) } else { controls.release(event.button) }
+    }
+  }
+
+  pub fn events(&self) -> &[ScriptEvent] {
+    &self.events
+  }
+}
+
+/// Frames a typed key holds its button, a terminal reports no key releases
+pub const KEY_HOLD_FRAMES: u64 = 6;
+
+/// Buttons pressed by keys as they arrive, see `Button::from_key`
+pub struct Keyboard {
+  keys: Receiver<u8>,
+  /// the buttons held down and the frame each is released at
+  held: Vec<(Button, u64)>,
+}
+
+impl Keyboard {
+  pub fn new(keys: Receiver<u8>) -> Keyboard {
+    Keyboard { keys, held: Vec::new() }
+  }
+
+  /// Keys typed on standard input, read on a thread so the emulation never
+  /// waits for them. A terminal in line mode sends them on Enter.
+  pub fn stdin() -> Keyboard {
+    let (sender, keys) = mpsc::channel();
+    thread::spawn(move || {
+      for byte in io::stdin().lock().bytes() {
+        match byte {
+          Ok(byte) if sender.send(byte).is_ok() => {},
+          _ => break,
+        }
+      }
+    });
+    Keyboard::new(keys)
+  }
+
+  /// Presses the buttons of the keys that arrived since the last frame and
+  /// releases the ones held for `KEY_HOLD_FRAMES`
+  pub fn apply(&mut self, frame: u64, controls: &mut Controls) {
+    for key in self.keys.try_iter() {
+      if let Some(button) = Button::from_key(key) {
+        controls.press(button);
+        self.held.retain(|&(held, _)| held != button);
+        self.held.push((button, frame + KEY_HOLD_FRAMES));
+      }
+    }
+    for &(button, release) in &self.held {
+      if release <= frame {
+        controls.release(button);
+      }
+    }
+    self.held.retain(|&(_, release)| release > frame);
+  }
+}
+
+/// "frame N: press BUTTON" or "frame N: release BUTTON"
+fn parse_line(line: &str) -> Result<ScriptEvent, String> {
+  let (frame, action) = match line.find(':') {
+    Some(colon) => (line[..colon].trim(), line[colon + 1..].trim()),
+    None => return Err(format!("expected \"frame N: press BUTTON\", got \"{}\"", line)),
+  };
+
+  let frame = match frame.split_whitespace().collect::<Vec<_>>()[..] {
+    ["frame", number] => number.parse().map_err(|_| format!("invalid frame number {}", number))?,
+    _ => return Err(format!("expected \"frame N\", got \"{}\"", frame)),
+  };
+  let (pressed, button) = match action.split_whitespace().collect::<Vec<_>>()[..] {
+    ["press", button] => (true, button.parse()?),
+    ["release", button] => (false, button.parse()?),
+    _ => return Err(format!("expected \"press BUTTON\" or \"release BUTTON\", got \"{}\"", action)),
+  };
+  Ok(ScriptEvent { frame, button, pressed })
+}
+
+#[test]
+fn controls_test() {
+  let mut controls = Controls::new();
+  assert_eq!(controls.port1(), 0x08);
+  controls.press(Button::Coin);
+  controls.press(Button::Right1);
+  assert_eq!(controls.port1(), 0x49);
+  assert_eq!(controls.port0(), 0x4e);
+  controls.release(Button::Coin);
+  controls.press(Button::Tilt);
+  assert_eq!(controls.port1(), 0x48);
+  assert_eq!(controls.port2(), 0x04);
+  assert_eq!(Controls::from_bits(controls.bits()), controls);
+}
+
+#[test]
+fn script_test() {
+  let script = Script::parse("
+    # insert a coin and start
+    frame 60: press COIN
+    frame 64: release COIN
+    frame 120: press start1   # 1 player
+    frame 60: press FIRE
+  ").unwrap();
+  assert_eq!(script.events().len(), 4);
+  assert_eq!(script.events()[1], ScriptEvent { frame: 60, button: Button::Fire1, pressed: true });
+
+  let mut controls = Controls::new();
+  script.apply(60, &mut controls);
+  assert!(controls.is_pressed(Button::Coin) && controls.is_pressed(Button::Fire1));
+  script.apply(64, &mut controls);
+  assert!(!controls.is_pressed(Button::Coin));
+
+  assert_eq!(Script::parse("frame 1: press START3"), Err("line 1: unknown button START3".to_string()));
+  assert_eq!(Script::parse("\nframe x: press COIN"), Err("line 2: invalid frame number x".to_string()));
+  assert!(Script::parse("press COIN").is_err());
+}
+
+#[test]
+fn keyboard_test() {
+  let (sender, keys) = mpsc::channel();
+  let mut keyboard = Keyboard::new(keys);
+  let mut controls = Controls::new();
+
+  sender.send(b'C').unwrap();
+  sender.send(b'x').unwrap();
+  keyboard.apply(10, &mut controls);
+  assert_eq!(controls.port1(), 0x09);
+  keyboard.apply(10 + KEY_HOLD_FRAMES - 1, &mut controls);
+  assert_eq!(controls.port1(), 0x09);
+  keyboard.apply(10 + KEY_HOLD_FRAMES, &mut controls);
+  assert_eq!(controls.port1(), 0x08);
+
+  sender.send(b'l').unwrap();
+  keyboard.apply(20, &mut controls);
+  assert_eq!(controls.port2(), 0x40);
+}
@@ -0,0 +1,67 @@
+//! The port devices of the Space Invaders board
+
+use std::io::{self, Read, Write};
+
+use i8080::Io;
+use i8080::savestate::{read_u16, write_u16};
+
+use dip_switches::DipSwitches;
+use input::Controls;
+use shift_register::ShiftRegister;
+use sound::SoundBoard;
+
+/// Everything behind IN and OUT on the Space Invaders board
+pub struct InvadersIo {
+  pub shift_register: ShiftRegister,
+  pub controls: Controls,
+  pub dip_switches: DipSwitches,
+  pub sound: SoundBoard,
+}
+
+impl InvadersIo {
+  pub fn new() -> InvadersIo {
+    InvadersIo { shift_register: ShiftRegister::new(), controls: Controls::new(), dip_switches: DipSwitches::new(), sound: SoundBoard::new() }
+  }
+}
+
+impl Default for InvadersIo {
+  fn default() -> InvadersIo {
+    InvadersIo::new()
+  }
+}
+
+impl Io for InvadersIo {
+  fn input(&mut self, port: u8) -> u8 {
+    match port {
+      0 => self.controls.port0(),
+      1 => self.controls.port1(),
+      2 => self.controls.port2() | self.dip_switches.port2(),
+      3 => self.shift_register.read_result(),
+      _ => 0x00,
+    }
+  }
+
+  fn output(&mut self, port: u8, value: u8) {
+    match port {
+      2 => self.shift_register.write_offset(value),
+      3 | 5 => self.sound.write(port, value),
+      4 => self.shift_register.write_data(value),
+      _ => {},
+    }
+  }
+
+  fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    self.shift_register.save_state(writer)?;
+    write_u16(writer, self.controls.bits())?;
+    writer.write_all(&self.sound.ports())
+  }
+
+  fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    self.shift_register.load_state(reader)?;
+    self.controls = Controls::from_bits(read_u16(reader)?);
+    let mut sound_ports = [0; 2];
+    reader.read_exact(&mut sound_ports)?;
+    self.sound.set_ports(sound_ports);
+    Ok(())
+  }
+}
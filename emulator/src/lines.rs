@@ -0,0 +1,22 @@
+//! Text files of one entry per line where `#` starts a comment: DIP switch
+//! settings, input scripts and ROM set descriptions
+
+/// Parses the lines that are not blank once the comment is cut off, in
+/// order. An error is prefixed with the number of the line it is about.
+pub fn parse_lines<T, F: FnMut(&str) -> Result<T, String>>(text: &str, mut parse_line: F) -> Result<Vec<T>, String> {
+  let mut entries = Vec::new();
+  for (index, line) in text.lines().enumerate() {
+    let line = line.split('#').next().unwrap().trim();
+    if !line.is_empty() {
+      entries.push(parse_line(line).map_err(|why| format!("line {}: {}", index + 1, why))?);
+    }
+  }
+  Ok(entries)
+}
+
+#[test]
+fn parse_lines_test() {
+  let parse = |line: &str| line.parse::<u8>().map_err(|_| format!("not a number: {}", line));
+  assert_eq!(parse_lines("# numbers\n1\n\n  2  # two\n", parse), Ok(vec![1, 2]));
+  assert_eq!(parse_lines("1\n#\nx", parse), Err("line 3: not a number: x".to_string()));
+}
@@ -8,613 +8,545 @@
 
 extern crate rustc_serialize;
 extern crate docopt;
-
+extern crate i8080;
+extern crate space_invaders;
+
+mod console;
+mod dip_switches;
+mod input;
+mod invaders;
+mod lines;
+mod ports;
+mod romset;
+mod scheduler;
+mod shift_register;
+mod sound;
+
+use std::io;
 use std::io::prelude::*;
+use std::io::BufWriter;
 use std::fs::{File};
-use std::num::Wrapping;
-
-// docopt!(Args derive Debug, "
-//   8080 Emulator – let's you emulat an intel 8080 CPU
-
-//   Usage:
-//   disassembler -i IFILE -o OFILE
-//   disassembler -h | --help
-//   disassembler -v | --version
-
-//   Options:
-//   -i IFILE --input=IFILE     Specify the input file
-//   -o OFILE --output=OFILE    Specivy the output file
-//   -h --help                  Show this screen.
-//   -v --version               Show version.
-//   ");
-
-// static VERSION: &'static str = "0.0.1";
-
-struct ConditionCode {
-    /// Zero: set if the result is zero
-    z: bool,
-    /// Sign: set if the result is negative
-    s: bool,
-    /// Parity: set if the number of 1 bits in the result is even
-    p: bool,
-    /// Carry: set if the last addition operation resulted in a carry, or
-    /// if the last subtraction operation required a borrow
-    cy: bool,
-    ac: bool,
-}
-
-struct CpuState {
-    // Register A: primary 8-bit accumulator
-    a: u8,
-    // Register B: either 8-bit single or B (BC) 16-bit register
-    b: u8,
-    c: u8,
-    d: u8,
-    e: u8,
-    h: u8,
-    l: u8,
-    sp: u16,
-    pc: u16,
-    memory: [u8; 0x10000],   //store 'memory' on heap?
-    cc: ConditionCode,
-    int_enable: u8,
-}
-
+use std::path::Path;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use i8080::{CpuState, Cpm, Image, Journal, MemoryMap, Movie, MovieIo, Rewind, init_cpu, init_cpu_with, disassemble_bus, save_state, load_state};
+use i8080::movie;
+
+use console::StdConsole;
+
+use dip_switches::DipSwitches;
+use input::{Keyboard, Script};
+use invaders::InvadersIo;
+use ports::Ports;
+use romset::RomSet;
+use scheduler::{Scheduler, VideoInterrupt};
+use sound::{Sample, Sound, SoundEvent};
+use space_invaders::video::Framebuffer;
+
+/// the emulated machine, memory journaled for stepping back, ports recorded for movies
+type Machine = CpuState<Journal<MemoryMap>, MovieIo<Ports>>;
+
+docopt!(Args derive Debug, "
+  8080 Emulator – let's you emulat an intel 8080 CPU
+
+  Usage:
+  emulator [options] [--dip=SETTING]... [--load=FILE]... [--bank=WINDOW]...
+  emulator -h | --help
+  emulator -v | --version
+
+  Options:
+  -r ROM --rom=ROM           Specify the ROM image [default: invaders.rom]
+  --rom-set=SET              Load split chip dumps instead: a set description file, or invaders
+  --rom-dir=DIR              Directory with the chip dumps [default: .]
+  --cpm=FILE                 Run a CP/M .COM program on the terminal instead of the Invaders machine
+  --load=FILE                Load a program into 64 KiB of plain RAM, with no Invaders ports or video interrupts, instead of the ROM: Intel HEX, S-records, or FILE@ADDRESS for a raw binary
+  --bank=WINDOW              Add a banked RAM window to the --load memory, START-END:BANKS@PORT with OUT PORT selecting the bank
+  --load-state=FILE          Resume from a save state instead of reset
+  --save-state=FILE          Write a save state when the emulation stops
+  --steps=N                  Stop after N instructions, 0 for no limit [default: 0]
+  --frames=N                 Stop after N frames, 0 for no limit [default: 0]
+  --history=N                Instructions kept for stepping back [default: 10000]
+  --back=N                   Step back N instructions when the emulation stops [default: 0]
+  --back-frames=N            Rewind N frames when the emulation stops, as far as --history reaches [default: 0]
+  --record=FILE              Record inputs and interrupts to a movie file
+  --replay=FILE              Replay a movie file recorded from the same start state
+  --protect-rom              Stop on writes to ROM instead of ignoring them
+  --input-script=FILE        Press buttons by frame, lines like 'frame 60: press COIN'
+  --keyboard                 Press buttons with keys typed on standard input: c coin, 1 2 start, a d space player 1, j l k player 2, t tilt
+  --dip=SETTING              Set a DIP switch: lives=3-6, bonus=1000|1500, coininfo=on|off
+  --dip-file=FILE            Read DIP switch settings from a file, one per line
+  --sound-log=FILE           Log every sound switching on or off with its cycle count
+  --samples=DIR              Directory with the sound samples 0.wav to 9.wav
+  --sound-wav=FILE           Mix the samples into a WAV file of the run, needs --samples
+  --screenshot=FILE          Write the screen as PNG, or PPM unless FILE ends in .png, when the emulation stops
+  -h --help                  Show this screen.
+  -v --version               Show version.
+  ");
+
+static VERSION: &'static str = "0.0.1";
 
 fn main() {
-  // let args: Args = Args::docopt().decode().unwrap_or_else(|e| e.exit());
-  // match args {
-  //   Args { flag_version: true, .. } => println!("version {}", VERSION),
-  //   Args {flag_input: input_file_path, flag_output: output_file_path, ..} => decode_file(input_file_path, output_file_path),
-  // }
+  let args: Args = Args::docopt().decode().unwrap_or_else(|e| e.exit());
+  if args.flag_version {
+    println!("version {}", VERSION);
+    return;
+  }
+  let steps: u64 = parse_number("--steps", &args.flag_steps);
+  let frames: u64 = parse_number("--frames", &args.flag_frames);
+  let history: usize = parse_number("--history", &args.flag_history);
+  let back: usize = parse_number("--back", &args.flag_back);
+  let back_frames: usize = parse_number("--back-frames", &args.flag_back_frames);
+  if !args.flag_cpm.is_empty() {
+    run_cpm(&args.flag_cpm, steps);
+    return;
+  }
+
+  if !args.flag_load.is_empty() {
+    let invaders_options = [
+      ("--dip", !args.flag_dip.is_empty()), ("--dip-file", !args.flag_dip_file.is_empty()),
+      ("--input-script", !args.flag_input_script.is_empty()), ("--keyboard", args.flag_keyboard),
+      ("--frames", frames > 0), ("--back-frames", back_frames > 0),
+      ("--sound-log", !args.flag_sound_log.is_empty()), ("--sound-wav", !args.flag_sound_wav.is_empty()),
+    ];
+    if let Some(&(option, _)) = invaders_options.iter().find(|&&(_, given)| given) {
+      panic!("{} needs the Invaders board, --load programs run without it", option);
+    }
+  }
 
   println!("running emulator");
-  let mut cpu_state = init_cpu();
-  load_rom_to_memory(&mut cpu_state);
+  let mut ports = Ports::new(if args.flag_load.is_empty() { Some(InvadersIo::new()) } else { None });
+  let memory = if !args.flag_load.is_empty() {
+    flat_memory(&args.flag_bank, &mut ports)
+  } else if args.flag_bank.is_empty() {
+    invaders_memory()
+  } else {
+    panic!("--bank needs --load, the Invaders memory has no room for bank windows");
+  };
+  let mut cpu_state = init_cpu_with(Journal::new(memory), MovieIo::new(ports));
+  cpu_state.memory.inner_mut().report_rom_writes = args.flag_protect_rom;
+  if let Some(invaders) = cpu_state.io.inner_mut().invaders.as_mut() {
+    set_dip_switches(&mut invaders.dip_switches, &args.flag_dip_file, &args.flag_dip);
+  }
+  let mut rewind = Rewind::new(history);
+  if !args.flag_load.is_empty() {
+    load_programs(&mut cpu_state, &args.flag_load);
+  } else if args.flag_rom_set.is_empty() {
+    load_rom_to_memory(&mut cpu_state, &args.flag_rom);
+  } else {
+    load_rom_set(&mut cpu_state, &args.flag_rom_set, &args.flag_rom_dir);
+  }
+  if !args.flag_load_state.is_empty() {
+    load_state_file(&mut cpu_state, &args.flag_load_state);
+  }
+  print!("{}", *cpu_state.memory);
+  if !args.flag_replay.is_empty() {
+    cpu_state.io.start_replay(load_movie_file(&args.flag_replay));
+  } else if !args.flag_record.is_empty() {
+    cpu_state.io.start_recording();
+  }
+
+  let script = if args.flag_input_script.is_empty() { Script::default() } else { load_script_file(&args.flag_input_script) };
+  let mut keyboard = if args.flag_keyboard { Some(Keyboard::stdin()) } else { None };
+  // the video interrupts come from the Invaders board
+  let mut scheduler = if args.flag_load.is_empty() { Some(Scheduler::new(cpu_state.cycles)) } else { None };
+  let mut frame_ctx: u64 = 0;
+  let mut debug_instruction_ctx: u64 = 0;
+  if let Some(invaders) = cpu_state.io.inner_mut().invaders.as_mut() {
+    script.apply(frame_ctx, &mut invaders.controls);
+  }
+
+  loop {
+    if args.flag_load.is_empty() && cpu_state.pc == 0x2000 {
+      println!("no more code to execute");
+      break;
+    }
+
+    movie::sync(&mut cpu_state);
+    if let Some(video_interrupt) = scheduler.as_mut().and_then(|scheduler| scheduler.poll(cpu_state.cycles)) {
+      movie::raise_interrupt(&mut cpu_state, video_interrupt.instruction());
+      if video_interrupt == VideoInterrupt::VBlank {
+        rewind.mark_frame();
+        frame_ctx += 1;
+        if frame_ctx == frames {
+          println!("stopped after {} frames", frames);
+          break;
+        }
+        if let Some(invaders) = cpu_state.io.inner_mut().invaders.as_mut() {
+          script.apply(frame_ctx, &mut invaders.controls);
+          if let Some(keyboard) = keyboard.as_mut() {
+            keyboard.apply(frame_ctx, &mut invaders.controls);
+          }
+        }
+      }
+    }
 
-  let mut done: i32 = 0;
-  let mut debug_instruction_ctx: i32 = 0;
+    if let Some(invaders) = cpu_state.io.inner_mut().invaders.as_mut() {
+      invaders.sound.set_cycles(cpu_state.cycles);
+    }
 
-  while done == 0 {
     // println!("emulate");
-    done = emulate(&mut cpu_state);
+    let _ = disassemble_bus(&cpu_state.memory, cpu_state.pc, &mut io::stdout());
+    if let Err(error) = rewind.step(&mut cpu_state) {
+      println!("emulation stopped: {}", error);
+      break;
+    }
+    print_state(&cpu_state);
     debug_instruction_ctx += 1;
     // println!("instr_ctx: {:?} \n", debug_instruction_ctx);
 
+    if debug_instruction_ctx == steps {
+      println!("stopped after {} instructions", steps);
+      break;
+    }
+
     //breakpoint
     if debug_instruction_ctx == 1548 {
       // panic!("breakpoint");
     }
   }
-}
 
+  if let Some(cycles) = cpu_state.io.desync() {
+    println!("replay went out of sync at cycle {}", cycles);
+  }
+  if !args.flag_record.is_empty() {
+    save_movie_file(cpu_state.io.take_movie(), &args.flag_record);
+  }
 
+  // walk back to see how the machine got here
+  if back_frames > 0 {
+    let undone = rewind.rewind_frames(&mut cpu_state, back_frames);
+    println!("rewound {} frames, {} instructions, to:", back_frames, undone);
+    let _ = disassemble_bus(&cpu_state.memory, cpu_state.pc, &mut io::stdout());
+    print_state(&cpu_state);
+  }
+  for _ in 0..back {
+    if !rewind.step_back(&mut cpu_state) {
+      println!("no more history to step back");
+      break;
+    }
+    println!("stepped back to:");
+    let _ = disassemble_bus(&cpu_state.memory, cpu_state.pc, &mut io::stdout());
+    print_state(&cpu_state);
+  }
 
-fn load_rom_to_memory(cpu_state: &mut CpuState) {
+  if let Some(invaders) = cpu_state.io.inner().invaders.as_ref() {
+    if !args.flag_sound_log.is_empty() {
+      write_sound_log(&invaders.sound.events, &args.flag_sound_log);
+    }
+    if !args.flag_sound_wav.is_empty() {
+      write_sound_wav(&invaders.sound.events, cpu_state.cycles, &args.flag_samples, &args.flag_sound_wav);
+    }
+  }
+  if !args.flag_screenshot.is_empty() {
+    write_screenshot(&cpu_state, &args.flag_screenshot);
+  }
+  if !args.flag_save_state.is_empty() {
+    save_state_file(&cpu_state, &args.flag_save_state);
+  }
+}
 
-  // let mut input_file = File::open("invaders.h").unwrap();
-  let mut input_file = File::open("invaders.rom").unwrap();
 
-  let mut buffer: Vec<u8> = Vec::new();
-  let file_size = input_file.read_to_end(&mut buffer).unwrap();
 
-  for (idx, byte) in buffer.iter().enumerate() {
-    cpu_state.memory[idx] = byte.clone();
+fn parse_number<T: FromStr>(option: &str, value: &str) -> T where T::Err: Display {
+  match value.parse() {
+    Err(why) => panic!("invalid {} {}: {}", option, value, why),
+    Ok(number) => number,
   }
 }
 
-fn init_cpu() -> CpuState {
-
-  let con_code = ConditionCode{ z:false, s:false, p:false, cy:false, ac:false, };
-
-  let cpu_state = CpuState{ 
-    a:0x00,
-    b:0x00,
-    c:0x00,
-    d:0x00,
-    e:0x00,
-    h:0x00,
-    l:0x00,
-    sp:0x0000,
-    pc:0x0000,
-    memory: [0; 0x10000],
-    cc: con_code,
-    int_enable: 0,
-  };
-
-  cpu_state
+/// the contents of a file named on the command line, panics if it cannot be read
+fn read_file(path: &str) -> Vec<u8> {
+  let mut data = Vec::new();
+  if let Err(why) = File::open(path).and_then(|mut file| file.read_to_end(&mut data)) {
+    panic!("could not read {}: {}", path, why);
+  }
+  data
 }
 
-fn emulate(cpu_state: &mut CpuState) -> i32 {
-
-  // println!("run emulator");
-
-  if cpu_state.pc == 0x2000 {
-    println!("no more code to execute");
-    return 1;
+fn read_text_file(path: &str) -> String {
+  match String::from_utf8(read_file(path)) {
+    Err(why) => panic!("could not read {}: {}", path, why),
+    Ok(text) => text,
   }
+}
 
-  // println!("code left");
-  disassemble(&cpu_state.memory, cpu_state.pc);
-
-  let operation_code = cpu_state.memory[cpu_state.pc as usize];
-  // possible out of bounds?
-  let operation_arg1 = cpu_state.memory[(cpu_state.pc + 1) as usize];
-  let operation_arg2 = cpu_state.memory[(cpu_state.pc + 2) as usize];
-
-  let mut operation_cycles = 0;
-
-  // println!("oa1: {:01$x}", operation_arg1, 2);
-  // println!("oa2: {:01$x}", operation_arg2, 2);
-
-  // println!("ca: {:01$x}", (operation_arg2 as u16) << 8 | (operation_arg1 as u16), 4);
-
-  cpu_state.pc+=1;
-
-  match operation_code {
-
-    //NOP ;4c ;os=1byte
-    0x00 => { operation_cycles = 4; },
-
-    //LXI B, u16   load immediate register pair BC   10c; os=3
-    0x01 => {  
-      cpu_state.c = operation_arg1;
-      cpu_state.b = operation_arg2;
-      cpu_state.pc += 2;
+/// creates a file named on the command line, panics if it cannot be created
+fn create_file(path: &str) -> BufWriter<File> {
+  match File::create(path) {
+    Err(why) => panic!("could not create {}: {}", path, why),
+    Ok(file) => BufWriter::new(file),
+  }
+}
 
-      operation_cycles = 10; 
-    },
+fn load_rom_to_memory(cpu_state: &mut Machine, rom_file_path: &str) {
+  cpu_state.memory.inner_mut().load(0x0000, &read_file(rom_file_path));
+}
 
-    //DRC B ; 5c; os=1byte decrement register B
-    0x05 => { 
-      let res = (Wrapping(cpu_state.b) - Wrapping(1)).0; // allow integer overflow
-      cpu_state.cc.z = res == 0;
-      cpu_state.cc.s = 0x80 == (res & 0x80);
-      cpu_state.cc.p = parity(res, 8);
-      cpu_state.b = res;
+fn load_rom_set(cpu_state: &mut Machine, rom_set_name: &str, rom_dir_path: &str) {
+  let rom_set = if rom_set_name == "invaders" {
+    RomSet::invaders()
+  } else {
+    match RomSet::parse(&read_text_file(rom_set_name)) {
+      Err(why) => panic!("invalid ROM set {}: {}", rom_set_name, why),
+      Ok(rom_set) => rom_set,
     }
+  };
 
-    //MVI B, u8  Move immediate value to B ;os=2byte
-    0x06 => { cpu_state.b = operation_arg1; cpu_state.pc += 1; }
-
-    //DAD B  add register pair BC to HL  ; 10c, ox=1
-    0x09 => {  
-
-      cpu_state.b = 0x01;
-
-      let bc: u32 = (cpu_state.b as u32) << 8 | cpu_state.c as u32;
-      let hl: u32 = (cpu_state.h as u32) << 8 | cpu_state.l as u32;
-
-      // println!("bc: {:01$x}", bc, 4);
-      // println!("hl: {:01$x}", hl, 4);
-
-      let res: u32 = bc + hl;
-
-      // println!("res: {:01$x}", res, 4);
-
-      cpu_state.h = ((res & 0xff00) >> 8) as u8;
-      cpu_state.l = (res & 0xff) as u8;
-      cpu_state.cc.cy = (res & 0xffff0000) > 0;
-
-      // println!("h: {:01$x}", cpu_state.h, 2);
-      // println!("l: {:01$x}", cpu_state.l, 2);
-
-      operation_cycles = 10; 
-
-      // panic!("break");
-    }, 
-
-    //DCR C  decrement single u8 register C 5c; os=1
-    0x0d => {  
-      let res: u8 = (Wrapping(cpu_state.c) - Wrapping(1)).0;
-      cpu_state.cc.z = res == 0;
-      cpu_state.cc.s = 0x80 == (res & 0x80);
-      cpu_state.cc.p = parity(res, 8);
-      cpu_state.c = res;
-
-      operation_cycles = 5;
-    },
-
-    //MVI C, u8 move immediate to C; 7c; os=2byte
-    0x0e => { cpu_state.c = operation_arg1; cpu_state.pc += 1; operation_cycles = 7;},
-
-    //LXI D, u16 ; 10c; os=3byte  load intermediate to combined register DE (just called D as 16 bit register)  
-    0x11 => { cpu_state.e = operation_arg1; cpu_state.d = operation_arg2; cpu_state.pc += 2; operation_cycles = 10; },
-
-    //INX D ;5c; os=1byte increment register DE
-    0x13 => { 
-      cpu_state.e = (Wrapping(cpu_state.e) + Wrapping(1)).0;
-      if cpu_state.e == 0 {
-        cpu_state.d = (Wrapping(cpu_state.d) + Wrapping(1)).0;
-      }
-
-      operation_cycles = 5;
-    },
-
-    //DAD D  add register pair DE to HL  ; 10c, ox=1
-    0x19 => {  
-      let de: u32 = (cpu_state.d as u32) << 8 | cpu_state.e as u32;
-      let hl: u32 = (cpu_state.h as u32) << 8 | cpu_state.l as u32;
-
-      // println!("de: {:01$x}", de, 4);
-      // println!("hl: {:01$x}", hl, 4);
-
-      let res: u32 = de + hl;
-
-      // println!("res: {:01$x}", res, 4);
-
-      cpu_state.h = ((res & 0xff00) >> 8) as u8;
-      cpu_state.l = (res & 0xff) as u8;
-      cpu_state.cc.cy = (res & 0xffff0000) != 0;
-
-      // println!("h: {:01$x}", cpu_state.h, 2);
-      // println!("l: {:01$x}", cpu_state.l, 2);
-
-      operation_cycles = 10; 
-
-      // panic!("break");
-    }, 
-
-    //NOP ;4c ;os=1byte
-    0x20 => { operation_cycles = 4; },
-
-    //LDAX D load ; 7c; os=1byte; load memory indirect from combinded register DE (just called D as 16 bit register) to A 
-    0x1a => { 
-      let memory_offset: u16 = ((cpu_state.d as u16) << 8) | (cpu_state.e as u16); 
-      // println!("{:01$x}", memory_offset, 4);
-      // println!("{:01$x}", cpu_state.memory[memory_offset as usize], 2);
-      cpu_state.a = cpu_state.memory[memory_offset as usize];
-      operation_cycles = 7; 
-    },
-
-    //MVI H, u8 move immediate to H; 7c; os=2byte
-    0x26 => { cpu_state.h = operation_arg1; cpu_state.pc += 1; operation_cycles = 7;},
-
-    //LXI H, u16 ; 10c; os=3byte  load intermediate to combined register HL (just called H as 16 bit register)
-    0x21 => { cpu_state.l = operation_arg1; cpu_state.h = operation_arg2; cpu_state.pc += 2; operation_cycles = 10; },
-
-    //INX H ;5c; os=1byte  increment register HL
-    0x23 => { 
-      cpu_state.l = (Wrapping(cpu_state.l) + Wrapping(1)).0 ; 
-      if cpu_state.l == 0 {
-        cpu_state.h = (Wrapping(cpu_state.h) + Wrapping(1)).0;
-      }
-
-      operation_cycles = 5;
-    },
-
-    //DAD H  add register pair HL to HL (HLx2) ; 10c, ox=1
-    0x29 => {  
-      let hl: u32 = (cpu_state.h as u32) << 8 | cpu_state.l as u32;
-      let res: u32 = hl + hl;
-      cpu_state.h = ((res & 0xff00) >> 8) as u8;
-      cpu_state.l = (res & 0xff) as u8;
-      cpu_state.cc.cy = (res & 0xffff0000) != 0;
-
-      operation_cycles = 10; 
-    }, 
-
-    //LXI sp, u16   Load registerpair u16 immediate to stack pointer(which is u16) ;10c ;os=3byte
-    0x31 => { cpu_state.sp = (operation_arg2 as u16) << 8 | (operation_arg1 as u16); cpu_state.pc += 2; operation_cycles = 10; },
-
-    //MVI M,byte move immediate memory; 10c; os=2byte 
-    0x36 => {
-      let offset: u16 = ((cpu_state.h as u16) << 8) | cpu_state.l as u16;
-      cpu_state.memory[offset as usize] = operation_arg1;
-      cpu_state.pc += 1;
-
-      operation_cycles = 10;
+  match rom_set.load(Path::new(rom_dir_path)) {
+    Err(why) => panic!("could not load ROM set {}: {}", rom_set_name, why),
+    Ok(image) => for segment in &image.segments {
+      cpu_state.memory.inner_mut().load(segment.address, &segment.data);
     },
+  }
+}
 
-    //LDA  load register A direct
-    0x3a => {
-      let offset: u16 = (operation_arg2 as u16) << 8 | operation_arg1 as u16;
-      cpu_state.a = cpu_state.memory[offset as usize];
-      cpu_state.pc += 2;// panic!("break");
-
-      operation_cycles = 13;
+/// loads every `--load` file, refusing bytes loaded twice, and starts at the
+/// start address of the last file naming one, else at the first file's first byte
+fn load_programs(cpu_state: &mut Machine, load_specs: &[String]) {
+  let mut image = Image::new();
+  for load_spec in load_specs {
+    // paths may hold an @ too, only an address after the last one splits the spec
+    let split = load_spec.rfind('@').and_then(|at| parse_address(&load_spec[at + 1..]).ok().map(|address| (at, address)));
+    let (path, address) = match split {
+      Some((at, address)) => (&load_spec[..at], Some(address)),
+      None => (&load_spec[..], None),
+    };
+    let data = read_file(path);
+
+    let loaded = match address {
+      Some(address) => Image::from_binary(address, &data),
+      None => {
+        let text = String::from_utf8_lossy(&data);
+        match text.trim_start().chars().next() {
+          Some(':') => Image::from_intel_hex(&text),
+          Some('S') => Image::from_srecord(&text),
+          _ => panic!("{} is neither Intel HEX nor S-records, give a raw binary a load address: {}@0x100", path, path),
+        }
+      },
+    };
+    if let Err(why) = loaded.and_then(|loaded| image.merge(loaded)) {
+      panic!("could not load {}: {}", path, why);
     }
+  }
 
-    //MOV L,A ; move from register A to L; 5c; os=1
-    0x6f => { cpu_state.l = cpu_state.a; operation_cycles = 5;}
-
-    //MOV M,A ; 7c; os=1  move content of register A to memory location pointed by register HL 16 bit ...
-    0x77 => {  
-      let memory_offset: u16 = ((cpu_state.h as u16) << 8) | (cpu_state.l as u16);
-      cpu_state.memory[memory_offset as usize] = cpu_state.a;
-      // println!("{:01$x}", memory_offset, 4);
-      // println!("{:01$x}", cpu_state.memory[memory_offset as usize], 2);
-      operation_cycles = 7; 
-    },
-
-    //MOV H,A ; 5c; os=1byte
-    0x7c => { cpu_state.a = cpu_state.h; operation_cycles = 5; }, 
-
-    //POP B  pop register pair BC 10c; os=1;
-    0xc1 => {  
-      cpu_state.c = cpu_state.memory[cpu_state.sp as usize];
-      cpu_state.b = cpu_state.memory[(cpu_state.sp + 1) as usize];
-      cpu_state.sp += 2;
-
-      operation_cycles = 10;
-    },
-
-    //JNZ adress u16 Jump on none zero ; 10c; os=3byte  
-    0xc2 => { 
-      if !cpu_state.cc.z {
-        cpu_state.pc = (operation_arg2 as u16) << 8 | (operation_arg1 as u16);
-      } else {
-        cpu_state.pc += 2;
-      }
-
-      operation_cycles = 10;
-    },
-
-    //JMP u16  jump to u16 adress ;10c ; os=3byte
-    0xc3 => { cpu_state.pc = (operation_arg2 as u16) << 8 | (operation_arg1 as u16); operation_cycles = 10; },
-
-    //PUSH B   push the register pair BC an the stack  11c; os=1
-    0xc5 => {  
-      cpu_state.memory[(cpu_state.sp - 1) as usize] = cpu_state.b;
-      cpu_state.memory[(cpu_state.sp - 2) as usize] = cpu_state.c;
-      cpu_state.sp -= 2;
-
-      operation_cycles = 11; 
-    },
-
-    //RET
-    0xc9 => {  
-      //load return adress from stack in to program counter
-      cpu_state.pc = cpu_state.memory[cpu_state.sp as usize] as u16 | ((cpu_state.memory[(cpu_state.sp + 1) as usize] as u16) << 8);
-      cpu_state.sp += 2; // remove address from stack
+  for segment in &image.segments {
+    println!("loaded {:04x}-{:04x}", segment.address, segment.address as usize + segment.data.len() - 1);
+    cpu_state.memory.inner_mut().load(segment.address, &segment.data);
+  }
+  match (image.start, image.segments.first()) {
+    (Some(start), _) => cpu_state.pc = start,
+    (None, Some(segment)) => {
+      println!("no start address, starting at {:04x}", segment.address);
+      cpu_state.pc = segment.address;
     },
+    (None, None) => {},
+  }
+}
 
-    //POP D  pop register pair DE 10c; os=1;
-    0xd1 => {  
-      cpu_state.e = cpu_state.memory[cpu_state.sp as usize];
-      cpu_state.d = cpu_state.memory[(cpu_state.sp + 1) as usize];
-      cpu_state.sp += 2;
-
-      operation_cycles = 10;
-    },
+/// 0x100 and $100 are hex, anything else decimal
+fn parse_address(value: &str) -> Result<u16, String> {
+  let hex = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).or_else(|| value.strip_prefix('$'));
+  match hex {
+    Some(digits) => u16::from_str_radix(digits, 16).map_err(|why| why.to_string()),
+    None => value.parse::<u16>().map_err(|why| why.to_string()),
+  }
+}
 
-    //CALL adr u16 ;17; os=3byte
-    0xcd => {  
-      let ret: u16 = cpu_state.pc + 2; // save return adress (3 byte after this 3 byte instr.) on the stack
-      // println!("{:01$x}", (ret >> 8) as u8, 4);
-      // println!("{:01$x}", ret as u8, 4);
-      cpu_state.memory[(cpu_state.sp - 1) as usize] = (ret >> 8) as u8; // -- as u8 == & 0xff -- bitmask lower 8 bits of return addr. to write in higher stack bits
-      cpu_state.memory[(cpu_state.sp - 2) as usize] = ret as u8; // -- as u8 == & -- 0xff bitmask higher 8 bits to write to lower stack bits
-      // println!("{:01$x}", cpu_state.memory[(cpu_state.sp - 1) as usize], 2);
-      // println!("{:01$x}", cpu_state.memory[(cpu_state.sp - 2) as usize], 2);
-
-      cpu_state.sp -= 2;  // stack grows down
-      cpu_state.pc = (operation_arg2 as u16) << 8 | (operation_arg1 as u16); // jump to destination
-      
-      operation_cycles = 17;
-    },
+/// a --bank window, START-END:BANKS@PORT like 0x8000-0xbfff:4@0x40
+fn parse_bank_window(spec: &str) -> Result<(u16, u16, usize, u8), String> {
+  let (range, rest) = spec.split_once(':').ok_or("no :BANKS")?;
+  let (banks, port) = rest.split_once('@').ok_or("no @PORT")?;
+  let (start, end) = range.split_once('-').ok_or("no START-END")?;
+  let (start, end, port) = (parse_address(start)?, parse_address(end)?, parse_address(port)?);
+  let banks = banks.parse::<usize>().map_err(|why| why.to_string())?;
+  if start > end {
+    return Err("the window ends before it starts".to_string());
+  }
+  if banks == 0 || port > 0xff {
+    return Err("expected at least one bank and a port up to 0xff".to_string());
+  }
+  Ok((start, end, banks, port as u8))
+}
 
-    //OUT u8  .. outputs the contend of register A to specified data port ... skip for now
-    0xd3 => { cpu_state.pc += 1; }
+/// runs a .COM program until it warm boots, with no tracing to keep the
+/// terminal to the program
+fn run_cpm(program_path: &str, steps: u64) {
+  let program = read_file(program_path);
+  let mut cpu_state = init_cpu();
+  let mut cpm = Cpm::new(StdConsole::new());
+  if let Err(why) = cpm.load(&mut cpu_state, &program) {
+    panic!("could not load {}: {}", program_path, why);
+  }
 
-    //PUSH D push register pair DE to stack; 11c; os=1byte
-    0xd5 => { 
-      cpu_state.memory[(cpu_state.sp - 1) as usize] = cpu_state.d;
-      cpu_state.memory[(cpu_state.sp - 2) as usize] = cpu_state.e;
-      cpu_state.sp -= 2;
+  let mut instructions: u64 = 0;
+  while !cpm.exited() {
+    if let Err(error) = cpm.step(&mut cpu_state) {
+      println!("\nemulation stopped: {}", error);
+      break;
+    }
+    instructions += 1;
+    if instructions == steps {
+      println!("\nstopped after {} instructions", steps);
+      break;
+    }
+  }
+  let _ = io::stdout().flush();
+  if let Some(error) = cpm.error() {
+    println!("\nthe program made a BDOS call that went wrong: {}", error);
+  }
+}
 
-      operation_cycles = 11; 
-    },
+/// the Space Invaders board: 8 KiB ROM, 1 KiB work RAM and 7 KiB video RAM,
+/// mirrored through the rest of the address space
+fn invaders_memory() -> MemoryMap {
+  let mut memory = MemoryMap::new();
+  memory.map_rom("rom", 0x0000, 0x1fff);
+  memory.map_ram("work ram", 0x2000, 0x23ff);
+  memory.map_ram("video ram", 0x2400, 0x3fff);
+  memory.map_mirror("ram mirror", 0x4000, 0xffff, 0x2000, 0x2000);
+  memory
+}
 
-    //POP H  pop register pair HL 10c; os=1;
-    0xe1 => {  
-      cpu_state.l = cpu_state.memory[cpu_state.sp as usize];
-      cpu_state.h = cpu_state.memory[(cpu_state.sp + 1) as usize];
-      cpu_state.sp += 2;
+/// what --load programs run in: RAM through all of the address space, so
+/// nothing is mirrored or write protected, around the --bank windows
+fn flat_memory(bank_specs: &[String], ports: &mut Ports) -> MemoryMap {
+  let mut windows = Vec::new();
+  for spec in bank_specs {
+    match parse_bank_window(spec) {
+      Err(why) => panic!("invalid --bank {}: {}", spec, why),
+      Ok(window) => windows.push(window),
+    }
+  }
+  windows.sort();
 
-      operation_cycles = 10;
-    },
+  let mut memory = MemoryMap::new();
+  let mut next: u32 = 0x0000;
+  for &(start, end, banks, port) in &windows {
+    if start as u32 > next {
+      memory.map_ram("ram", next as u16, start - 1);
+    }
+    memory.map_banked("bank window", start, end, banks, ports.bank_register(port));
+    next = end as u32 + 1;
+  }
+  if next <= 0xffff {
+    memory.map_ram("ram", next as u16, 0xffff);
+  }
+  memory
+}
 
-    //PUSH H push register pair HL to stack; 11c; os=1byte
-    0xe5 => { 
-      cpu_state.memory[(cpu_state.sp - 1) as usize] = cpu_state.h;
-      cpu_state.memory[(cpu_state.sp - 2) as usize] = cpu_state.l;
-      cpu_state.sp -= 2;
+fn load_state_file(cpu_state: &mut Machine, state_file_path: &str) {
+  let data = read_file(state_file_path);
+  if let Err(why) = load_state(cpu_state, &mut &data[..]) {
+    panic!("could not load save state {}: {}", state_file_path, why);
+  }
+  println!("resumed from {} at pc {:04x}", state_file_path, cpu_state.pc);
+}
 
-      operation_cycles = 11; 
-    },
+fn save_state_file(cpu_state: &Machine, state_file_path: &str) {
+  let mut state_file = create_file(state_file_path);
+  if let Err(why) = save_state(cpu_state, &mut state_file).and_then(|_| state_file.flush()) {
+    panic!("could not write save state {}: {}", state_file_path, why);
+  }
+  println!("saved state to {}", state_file_path);
+}
 
-    //XCHG   exchange register pairs DE <-> HL 5c; os=1
-    0xeb => {  
-      let d: u8 = cpu_state.d;
-      let e: u8 = cpu_state.e;
-      cpu_state.d = cpu_state.h;
-      cpu_state.e = cpu_state.l;
-      cpu_state.h = d;
-      cpu_state.l = e;
+fn set_dip_switches(dip_switches: &mut DipSwitches, dip_file_path: &str, settings: &[String]) {
+  if !dip_file_path.is_empty() {
+    if let Err(why) = dip_switches.set_from_file(&read_text_file(dip_file_path)) {
+      panic!("invalid DIP switch file {}: {}", dip_file_path, why);
+    }
+  }
+  // the command line overrides the file
+  for setting in settings {
+    if let Err(why) = dip_switches.set(setting) {
+      panic!("invalid --dip {}: {}", setting, why);
+    }
+  }
+  println!("DIP switches: {}", dip_switches);
+}
 
-      operation_cycles = 5;
-    },
+fn load_script_file(script_file_path: &str) -> Script {
+  match Script::parse(&read_text_file(script_file_path)) {
+    Err(why) => panic!("invalid input script {}: {}", script_file_path, why),
+    Ok(script) => script,
+  }
+}
 
-    //CPI byte compare immediate with A ;7c ; os=2byte
-    0xfe => {  
-      let cpm: u8 = (Wrapping(cpu_state.a) - Wrapping(operation_arg1)).0;
-      cpu_state.cc.z = cpm == 0;
-      cpu_state.cc.s = 0x80 == ( cpm & 0x80 );
-      cpu_state.cc.p = parity(cpm, 8);
-      cpu_state.cc.cy = cpu_state.a < operation_arg1;
-      cpu_state.pc += 1;
+fn load_movie_file(movie_file_path: &str) -> Movie {
+  let data = read_file(movie_file_path);
+  match Movie::load(&mut &data[..]) {
+    Err(why) => panic!("could not load movie {}: {}", movie_file_path, why),
+    Ok(movie) => movie,
+  }
+}
 
-      operation_cycles = 7; 
-    },
-    
-    _ => panic!("the opcode: {:01$x} is not yet implemented, shutting down vm.", operation_code, 2),
+fn save_movie_file(movie: Movie, movie_file_path: &str) {
+  let mut movie_file = create_file(movie_file_path);
+  if let Err(why) = movie.save(&mut movie_file).and_then(|_| movie_file.flush()) {
+    panic!("could not write movie {}: {}", movie_file_path, why);
   }
-  println!("z:{:?} s:{:?} p:{:?} cy:{:?} ac:{:?}",cpu_state.cc.z, cpu_state.cc.s, cpu_state.cc.p, cpu_state.cc.cy, cpu_state.cc.ac );
-  println!("A:{:09$x} B:{:09$x} C:{:09$x} D:{:09$x} E:{:09$x} H:{:09$x} L:{:09$x} SP:{:010$x} PC:{:010$x}", cpu_state.a, cpu_state.b, cpu_state.c, cpu_state.d, cpu_state.e, cpu_state.h, cpu_state.l, cpu_state.sp, cpu_state.pc, 2, 4);
-  // println!("Stack u16:{:01$x}", cpu_state.memory[cpu_state.sp as usize] as u16 | ((cpu_state.memory[(cpu_state.sp + 1) as usize] as u16) << 8), 4);
-  println!("\n");
-  return 0;
+  println!("recorded {} events to {}", movie.events.len(), movie_file_path);
 }
 
-#[test]
-fn parity_test() {
+fn write_sound_log(events: &[SoundEvent], log_file_path: &str) {
+  let mut log_file = create_file(log_file_path);
+  for event in events {
+    if let Err(why) = writeln!(log_file, "{}", event) {
+      panic!("could not write sound log {}: {}", log_file_path, why);
+    }
+  }
+  if let Err(why) = log_file.flush() {
+    panic!("could not write sound log {}: {}", log_file_path, why);
+  }
+  println!("logged {} sound events to {}", events.len(), log_file_path);
+}
 
-  assert_eq!(parity(0u8, 8), true); // zero is even .. ?
-  assert_eq!(parity(1u8, 8), false);
-  assert_eq!(parity(2u8, 8), false);
-  assert_eq!(parity(3u8, 8), true);
-  assert_eq!(parity(4u8, 8), false);
-  assert_eq!(parity(5u8, 8), true);
-  assert_eq!(parity(6u8, 8), true);
-  assert_eq!(parity(7u8, 8), false);
-  assert_eq!(parity(8u8, 8), false);
-  assert_eq!(parity(9u8, 8), true);
+fn write_sound_wav(events: &[SoundEvent], cycles: u64, samples_path: &str, wav_file_path: &str) {
+  if samples_path.is_empty() {
+    panic!("--sound-wav needs --samples");
+  }
+  let mut samples = Vec::new();
+  for sound in Sound::all() {
+    let sample_path = Path::new(samples_path).join(sound.sample_name());
+    match File::open(&sample_path).and_then(|mut file| Sample::read_wav(&mut file)) {
+      Err(why) => println!("no sample for {:?}, {}: {}", sound, sample_path.display(), why),
+      Ok(sample) => samples.push((sound, sample)),
+    }
+  }
 
+  let mixed = sound::mix(events, &samples, cycles);
+  let mut wav_file = create_file(wav_file_path);
+  if let Err(why) = sound::write_wav(&mut wav_file, &mixed).and_then(|_| wav_file.flush()) {
+    panic!("could not write {}: {}", wav_file_path, why);
+  }
+  println!("mixed sound to {}", wav_file_path);
 }
 
-/// counts the number of 1 in binary format
-fn parity(_x: u8, size: usize) -> bool {
-  let mut p = 0;      //number of ones
-  let mut x = _x;
+fn write_screenshot(cpu_state: &Machine, image_file_path: &str) {
+  let mut framebuffer = Framebuffer::new();
+  framebuffer.render(&cpu_state.memory);
 
-  for i in 0..size {  // count every diget if its a one
-    if 1 == (x & 0x1) { p += 1; }
-    x = x >> 1;
+  let mut image_file = create_file(image_file_path);
+  let written = if image_file_path.ends_with(".png") {
+    framebuffer.write_png(&mut image_file)
+  } else {
+    framebuffer.write_ppm(&mut image_file)
+  };
+  if let Err(why) = written.and_then(|_| image_file.flush()) {
+    panic!("could not write screenshot {}: {}", image_file_path, why);
   }
-
-  0 == (p & 0x1)      // true if the count is even
+  println!("wrote screenshot to {}", image_file_path);
 }
 
-
-fn disassemble(instruction_buffer: &[u8], program_counter: u16) -> u16 {
-
-  let operation_code = instruction_buffer[program_counter as usize];
-  // possible out of bounds?
-  let operation_arg1 = instruction_buffer[(program_counter + 1) as usize];
-  let operation_arg2 = instruction_buffer[(program_counter + 2) as usize];
-  let mut operation_size = 1;
-
-  let mut output = Vec::new();
-  write!(&mut output, "{:01$x}: \t", program_counter, 4);
-
-  match operation_code {
-    0x00 => { write!(&mut output, "NOP\n"); },
-    0x01 => { write!(&mut output, "LXI \tB, #${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x04 => { write!(&mut output, "INR \tB\n");},
-    0x05 => { write!(&mut output, "DCR \tB\n");},
-    0x06 => { write!(&mut output, "MVI \tB, #${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0x07 => { write!(&mut output, "RLC\n"); },
-    0x09 => { write!(&mut output, "DAD \tB\n");},
-    0x0d => { write!(&mut output, "DCR \tC\n");},
-    0x0e => { write!(&mut output, "MVI \tC, #${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0x0f => { write!(&mut output, "RRC\n"); },
-
-    0x11 => { write!(&mut output, "LXI \tD, #${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x13 => { write!(&mut output, "INX \tD\n");},
-    0x14 => { write!(&mut output, "INR \tD\n");},
-    0x15 => { write!(&mut output, "DCR \tD\n");},
-    0x16 => { write!(&mut output, "MVI \tD, #${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0x19 => { write!(&mut output, "DAD \tD\n");},
-    0x1a => { write!(&mut output, "LDAX \tD\n");},
-
-    0x20 => { write!(&mut output, "NOP\n"); },
-    0x21 => { write!(&mut output, "LXI \tH, #${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x22 => { write!(&mut output, "SHLD \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x23 => { write!(&mut output, "INX \tH\n");},
-    0x26 => { write!(&mut output, "MVI \tH #${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0x27 => { write!(&mut output, "DAA \n"); },
-    0x29 => { write!(&mut output, "DAD \tH \n"); },
-    0x2a => { write!(&mut output, "LHLD \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x2b => { write!(&mut output, "DCX \tH\n");},
-    0x2c => { write!(&mut output, "INR \tL\n");},
-    0x2e => { write!(&mut output, "MVI \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-
-    0x31 => { write!(&mut output, "LXI \tSP, ${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x32 => { write!(&mut output, "STA \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x34 => { write!(&mut output, "INR \tM\n");},
-    0x35 => { write!(&mut output, "DCR \tM\n");},
-    0x36 => { write!(&mut output, "MVI \tM, #${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0x3a => { write!(&mut output, "LDA \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0x3c => { write!(&mut output, "INR \tA\n");}
-    0x3d => { write!(&mut output, "DCR \tA\n");}
-    0x3e => { write!(&mut output, "MVI \tA, #${:01$x}\n", operation_arg1, 2); operation_size = 2},
-
-    0x46 => { write!(&mut output, "MOV \tB, M\n");},
-    0x47 => { write!(&mut output, "MOV \tB, A\n");},
-    0x4e => { write!(&mut output, "MOV \tC, M\n");},
-    0x4f => { write!(&mut output, "MOV \tC, A\n");},
-
-    0x56 => { write!(&mut output, "MOV \tD, M\n");},
-    0x5e => { write!(&mut output, "MOV \tE, M\n");},
-    0x5f => { write!(&mut output, "MOV \tE, A\n");},
-
-    0x61 => { write!(&mut output, "MOV \tH, C\n");},
-    0x66 => { write!(&mut output, "MOV \tH, M\n");},
-    0x67 => { write!(&mut output, "MOV \tH, A\n");},
-    0x68 => { write!(&mut output, "MOV \tL, B\n");},
-    0x6f => { write!(&mut output, "MOV \tL, A\n");},
-
-    0x70 => { write!(&mut output, "MOV \tM, B\n");},
-    0x72 => { write!(&mut output, "MOV \tM, D\n");},
-    0x73 => { write!(&mut output, "MOV \tM, E\n");},
-    0x77 => { write!(&mut output, "MOV \tM, A\n");},
-    0x78 => { write!(&mut output, "MOV \tA, B\n");},
-    0x79 => { write!(&mut output, "MOV \tA, C\n");},
-    0x7a => { write!(&mut output, "MOV \tA, D\n");},
-    0x7b => { write!(&mut output, "MOV \tA, E\n");},
-    0x7c => { write!(&mut output, "MOV \tA, H\n");},
-    0x7d => { write!(&mut output, "MOV \tA, L\n");},
-    0x7e => { write!(&mut output, "MOV \tA, M\n");},
-
-    0x85 => { write!(&mut output, "ADD \tL\n");},
-    0x86 => { write!(&mut output, "ADD \tM\n");},
-    0x8d => { write!(&mut output, "ADC \tL\n");},
-
-    0xa1 => { write!(&mut output, "ANA \tC\n");},
-    0xa7 => { write!(&mut output, "ANA \tA\n");},
-    0xaf => { write!(&mut output, "XRA \tA\n");},
-
-    0xb0 => { write!(&mut output, "DCX \tB\n");},
-
-    0xc0 => { write!(&mut output, "RNZ \n"); },
-    0xc1 => { write!(&mut output, "POP \tB \n"); },
-    0xc2 => { write!(&mut output, "JNZ \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xc3 => { write!(&mut output, "JMP \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xc4 => { write!(&mut output, "CNZ \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xc5 => { write!(&mut output, "PUSH \tB\n"); },
-    0xc6 => { write!(&mut output, "ADI \t#${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0xc8 => { write!(&mut output, "RZ \n"); },
-    0xc9 => { write!(&mut output, "RET \n"); },
-    0xca => { write!(&mut output, "JZ \t\t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xcc => { write!(&mut output, "CZ \t\t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xcd => { write!(&mut output, "CALL \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-
-    0xd0 => { write!(&mut output, "RNC \tD\n"); },
-    0xd1 => { write!(&mut output, "POP \tD\n"); },
-    0xd2 => { write!(&mut output, "JNC \t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xd3 => { write!(&mut output, "OUT \t#${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0xd5 => { write!(&mut output, "PUSH \tD\n"); },
-    0xda => { write!(&mut output, "JC \t\t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xdb => { write!(&mut output, "IN \t\t#${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0xde => { write!(&mut output, "SBI \t#${:01$x}\n", operation_arg1, 2); operation_size = 2},
-
-    0xe1 => { write!(&mut output, "POP \tH \n"); },
-    0xe3 => { write!(&mut output, "XTHL \n"); },
-    0xe5 => { write!(&mut output, "PUSH \tH\n"); },
-    0xe6 => { write!(&mut output, "ANI \t#${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    0xe9 => { write!(&mut output, "PCHL \n"); },
-    0xeb => { write!(&mut output, "XCHG \n"); },
-
-    0xf1 => { write!(&mut output, "POP \tPSW \n"); },
-    0xf5 => { write!(&mut output, "PUSH \tPSW\n"); },
-    0xfa => { write!(&mut output, "JM \t\t${:02$x}{:02$x}\n", operation_arg2, operation_arg1, 2); operation_size = 3 },
-    0xfb => { write!(&mut output, "EI \n"); },
-    0xfe => { write!(&mut output, "CPI \t#${:01$x}\n", operation_arg1, 2); operation_size = 2},
-    _ => panic!("can not disassemble: {:01$x}", operation_code, 2)
-  }
-
-  let s = String::from_utf8(output).unwrap();
-  print!("{}", s);
-  return operation_size;
-}
\ No newline at end of file
+fn print_state(cpu_state: &Machine) {
+  println!("z:{:?} s:{:?} p:{:?} cy:{:?} ac:{:?}",cpu_state.cc.z, cpu_state.cc.s, cpu_state.cc.p, cpu_state.cc.cy, cpu_state.cc.ac );
+  println!("A:{:09$x} B:{:09$x} C:{:09$x} D:{:09$x} E:{:09$x} H:{:09$x} L:{:09$x} SP:{:010$x} PC:{:010$x}", cpu_state.a, cpu_state.b, cpu_state.c, cpu_state.d, cpu_state.e, cpu_state.h, cpu_state.l, cpu_state.sp, cpu_state.pc, 2, 4);
+  for region in cpu_state.memory.regions() {
+    if let Some(bank) = cpu_state.memory.active_bank(region.start) {
+      println!("{}: bank {}", region.name, bank);
+    }
+  }
+  // println!("Stack u16:{:01$x}", cpu_state.memory[cpu_state.sp as usize] as u16 | ((cpu_state.memory[(cpu_state.sp + 1) as usize] as u16) << 8), 4);
+  println!("\n");
+}
@@ -0,0 +1,88 @@
+//! What the emulator puts behind IN and OUT: the Space Invaders devices, or
+//! nothing for --load programs, with the bank latches of the --bank windows
+//! in front of them
+
+use std::io::{self, Read, Write};
+
+use i8080::{BankRegister, Io};
+
+use invaders::InvadersIo;
+
+pub struct Ports {
+  /// None for a plain machine, where IN reads 0 and OUT is ignored
+  pub invaders: Option<InvadersIo>,
+  /// OUT to the port selects the bank of every window sharing the register
+  bank_latches: Vec<(u8, BankRegister)>,
+}
+
+impl Ports {
+  pub fn new(invaders: Option<InvadersIo>) -> Ports {
+    Ports { invaders, bank_latches: Vec::new() }
+  }
+
+  /// The register OUT `port` sets, created the first time `port` is named.
+  /// The latch hides whatever device was on that port.
+  pub fn bank_register(&mut self, port: u8) -> BankRegister {
+    if let Some(register) = self.bank_latch(port) {
+      return register.clone();
+    }
+    let register = BankRegister::new();
+    self.bank_latches.push((port, register.clone()));
+    register
+  }
+
+  fn bank_latch(&self, port: u8) -> Option<&BankRegister> {
+    self.bank_latches.iter().find(|&&(latch, _)| latch == port).map(|(_, register)| register)
+  }
+}
+
+// the selected banks are saved with the memory map
+impl Io for Ports {
+  fn input(&mut self, port: u8) -> u8 {
+    match self.bank_latch(port) {
+      Some(register) => register.get(),
+      None => self.invaders.as_mut().map_or(0x00, |invaders| invaders.input(port)),
+    }
+  }
+
+  fn output(&mut self, port: u8, value: u8) {
+    match self.bank_latch(port) {
+      Some(register) => register.set(value),
+      None => if let Some(invaders) = self.invaders.as_mut() {
+        invaders.output(port, value);
+      },
+    }
+  }
+
+  fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    match self.invaders {
+      Some(ref invaders) => invaders.save_state(writer),
+      None => Ok(()),
+    }
+  }
+
+  fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    match self.invaders {
+      Some(ref mut invaders) => invaders.load_state(reader),
+      None => Ok(()),
+    }
+  }
+}
+
+#[test]
+fn ports_test() {
+  let mut ports = Ports::new(Some(InvadersIo::new()));
+  let register = ports.bank_register(0x40);
+  assert_eq!(ports.bank_register(0x40).get(), 0);
+  ports.output(0x40, 3);
+  assert_eq!(register.get(), 3);
+  assert_eq!(ports.input(0x40), 3);
+
+  // the shift register is still on ports 2, 3 and 4
+  ports.output(4, 0xab);
+  assert_eq!(ports.input(3), 0xab);
+
+  let mut plain = Ports::new(None);
+  plain.output(4, 0xab);
+  assert_eq!(plain.input(3), 0x00);
+}
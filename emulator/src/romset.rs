@@ -0,0 +1,135 @@
+//! ROM sets: which chip dump goes to which address. Dumps come one file per
+//! chip, e.g. invaders.h, .g, .f and .e, each 2 KiB. A set can be described
+//! in a text file, one chip per line with file name, load address, size and
+//! CRC-32 in hex, `#` starting a comment:
+//!
+//! ```text
+//! invaders.h  0000  0800  734f5ad8
+//! ```
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use i8080::Image;
+use space_invaders::crc32::crc32;
+
+use lines::parse_lines;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomChip {
+  pub file: String,
+  pub address: u16,
+  pub size: usize,
+  pub crc32: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomSet {
+  pub chips: Vec<RomChip>,
+}
+
+impl RomSet {
+  /// The Space Invaders set as MAME knows it
+  pub fn invaders() -> RomSet {
+    let chip = |file: &str, address: u16, crc32: u32| RomChip { file: file.to_string(), address, size: 0x0800, crc32 };
+    RomSet {
+      chips: vec![
+        chip("invaders.h", 0x0000, 0x734f_5ad8),
+        chip("invaders.g", 0x0800, 0x6bfa_ca4a),
+        chip("invaders.f", 0x1000, 0x0cce_ad96),
+        chip("invaders.e", 0x1800, 0x14e5_38b0),
+      ],
+    }
+  }
+
+  /// Parses a set description, see the module docs
+  pub fn parse(text: &str) -> Result<RomSet, String> {
+    Ok(RomSet { chips: parse_lines(text, parse_chip)? })
+  }
+
+  /// Reads every chip from `directory` and checks its size and CRC-32, and
+  /// that no chip overlaps another
+  pub fn load(&self, directory: &Path) -> Result<Image, String> {
+    let mut image = Image::new();
+    for chip in &self.chips {
+      let path = directory.join(&chip.file);
+      let mut data = Vec::new();
+      if let Err(why) = File::open(&path).and_then(|mut file| file.read_to_end(&mut data)) {
+        return Err(format!("could not read {}: {}", path.display(), why));
+      }
+      chip.verify(&data)?;
+      image.add(chip.address, &data).map_err(|why| format!("{}: {}", chip.file, why))?;
+    }
+    Ok(image)
+  }
+}
+
+/// "file address size crc32", the numbers in hex
+fn parse_chip(line: &str) -> Result<RomChip, String> {
+  match line.split_whitespace().collect::<Vec<_>>()[..] {
+    [file, address, size, crc32] => {
+      let hex = |field: &str, value: &str| u32::from_str_radix(value, 16).map_err(|_| format!("invalid {} {}", field, value));
+      let address = hex("address", address)?;
+      if address > 0xffff {
+        return Err(format!("address {:x} beyond 64 KiB", address));
+      }
+      let size = hex("size", size)?;
+      if address + size > 0x10000 {
+        return Err(format!("chip of {:x} bytes at {:x} runs beyond 64 KiB", size, address));
+      }
+      Ok(RomChip { file: file.to_string(), address: address as u16, size: size as usize, crc32: hex("CRC-32", crc32)? })
+    },
+    _ => Err(format!("expected \"file address size crc32\", got \"{}\"", line)),
+  }
+}
+
+impl RomChip {
+  /// Checks a dump of this chip
+  pub fn verify(&self, data: &[u8]) -> Result<(), String> {
+    if data.len() != self.size {
+      return Err(format!("{} is {} bytes, expected {}", self.file, data.len(), self.size));
+    }
+    let crc = crc32(data);
+    if crc != self.crc32 {
+      return Err(format!("{} has CRC-32 {:08x}, expected {:08x}", self.file, crc, self.crc32));
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn romset_test() {
+  use std::{env, fs, process};
+  use i8080::Segment;
+
+  let rom_set = RomSet::parse("
+    # a made up two chip set
+    a.bin 0000 0004 cbf43926  # crc of the data below
+    b.bin 1800 800 0
+  ").unwrap();
+  assert_eq!(rom_set.chips[1], RomChip { file: "b.bin".to_string(), address: 0x1800, size: 0x0800, crc32: 0 });
+
+  let chip = RomChip { file: "c.bin".to_string(), address: 0, size: 9, crc32: 0xcbf4_3926 };
+  assert_eq!(chip.verify(b"123456789"), Ok(()));
+  assert_eq!(chip.verify(b"12345678"), Err("c.bin is 8 bytes, expected 9".to_string()));
+  assert_eq!(chip.verify(b"123456780"), Err(format!("c.bin has CRC-32 {:08x}, expected cbf43926", crc32(b"123456780"))));
+
+  assert_eq!(RomSet::parse("a.bin 10000 1 0"), Err("line 1: address 10000 beyond 64 KiB".to_string()));
+  assert_eq!(RomSet::parse("a.bin f800 801 0"), Err("line 1: chip of 801 bytes at f800 runs beyond 64 KiB".to_string()));
+  assert!(RomSet::parse("a.bin 0000").is_err());
+  assert_eq!(RomSet::invaders().chips.iter().map(|chip| chip.address).collect::<Vec<_>>(), vec![0x0000, 0x0800, 0x1000, 0x1800]);
+
+  let missing = RomSet::invaders().load(Path::new("/nonexistent"));
+  assert!(missing.unwrap_err().starts_with("could not read /nonexistent/invaders.h"));
+
+  let directory = env::temp_dir().join(format!("romset_test_{}", process::id()));
+  fs::create_dir_all(&directory).unwrap();
+  fs::write(directory.join("a.bin"), b"1234").unwrap();
+  let chip = |address: u16| RomChip { file: "a.bin".to_string(), address, size: 4, crc32: crc32(b"1234") };
+  let loaded = RomSet { chips: vec![chip(0x0000), chip(0x0004)] }.load(&directory);
+  let overlapping = RomSet { chips: vec![chip(0x0000), chip(0x0002)] }.load(&directory);
+  fs::remove_dir_all(&directory).unwrap();
+  assert_eq!(loaded.unwrap().segments, vec![Segment { address: 0x0000, data: b"12341234".to_vec() }]);
+  assert_eq!(overlapping, Err("a.bin: 0002 is loaded twice".to_string()));
+}
@@ -0,0 +1,77 @@
+//! Timing of the Space Invaders board. The 8080 runs at 2 MHz and the video
+//! hardware draws 60 frames a second. When the beam reaches the middle of
+//! the screen the board raises RST 1, at the start of vertical blank RST 2,
+//! so the game can redraw the half of the screen the beam is not on.
+
+/// T-states per second
+pub const CLOCK_HZ: u64 = 2_000_000;
+/// Frames per second
+pub const FRAME_RATE: u64 = 60;
+
+/// An interrupt the video hardware raises
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VideoInterrupt {
+  /// RST 1, the beam is in the middle of the screen
+  MidScreen,
+  /// RST 2, the beam finished the frame
+  VBlank,
+}
+
+impl VideoInterrupt {
+  /// What the board puts on the data bus
+  pub fn instruction(self) -> [u8; 3] {
+    match self {
+      VideoInterrupt::MidScreen => [0xcf, 0x00, 0x00],  // RST 1
+      VideoInterrupt::VBlank => [0xd7, 0x00, 0x00],     // RST 2
+    }
+  }
+}
+
+/// Raises the video interrupts from the cycle counter. It keeps no state
+/// besides the last half frame seen, so it picks up after a save state.
+pub struct Scheduler {
+  half_frame: u64,
+}
+
+impl Scheduler {
+  /// Starting at `cycles` T-states
+  pub fn new(cycles: u64) -> Scheduler {
+    Scheduler { half_frame: half_frame(cycles) }
+  }
+
+  /// The interrupt due once `cycles` T-states have passed, if any. Call it
+  /// between instructions; a half frame is long enough that at most one is due.
+  pub fn poll(&mut self, cycles: u64) -> Option<VideoInterrupt> {
+    let half_frame = half_frame(cycles);
+    if half_frame == self.half_frame {
+      return None;
+    }
+    self.half_frame = half_frame;
+    if half_frame % 2 == 1 { Some(VideoInterrupt::MidScreen) } else { Some(VideoInterrupt::VBlank) }
+  }
+}
+
+/// half frames since reset, without accumulating rounding errors
+fn half_frame(cycles: u64) -> u64 {
+  cycles * FRAME_RATE * 2 / CLOCK_HZ
+}
+
+#[test]
+fn scheduler_test() {
+  let mut scheduler = Scheduler::new(0);
+  let mut raised = Vec::new();
+  let mut cycles = 0;
+  // one second in instructions of 7 T-states
+  while cycles < CLOCK_HZ {
+    cycles += 7;
+    if let Some(interrupt) = scheduler.poll(cycles) {
+      raised.push((cycles, interrupt));
+    }
+  }
+  assert_eq!(raised.len(), 2 * FRAME_RATE as usize);
+  assert_eq!(raised[0].1, VideoInterrupt::MidScreen);
+  assert!(raised[0].0 >= CLOCK_HZ / FRAME_RATE / 2);
+  assert_eq!(raised[1].1, VideoInterrupt::VBlank);
+  assert!(raised.windows(2).all(|pair| pair[0].1 != pair[1].1));
+  assert_eq!(VideoInterrupt::VBlank.instruction()[0], 0xd7);
+}
@@ -0,0 +1,66 @@
+//! The dedicated shift register of the Space Invaders board. The 8080 has no
+//! barrel shifter, so the board shifts sprites for it: OUT 4 pushes a byte into
+//! a 16 bit register, OUT 2 sets an offset of 0-7 and IN 3 reads the 8 bits
+//! starting that many bits below the top.
+
+use std::io::{self, Read, Write};
+
+use i8080::savestate::{read_u8, read_u16, write_u16};
+
+#[derive(Default)]
+pub struct ShiftRegister {
+  value: u16,
+  offset: u8,
+}
+
+impl ShiftRegister {
+  pub fn new() -> ShiftRegister {
+    ShiftRegister::default()
+  }
+
+  /// OUT 4: the new byte becomes the high byte, the old high byte the low byte
+  pub fn write_data(&mut self, data: u8) {
+    self.value = (data as u16) << 8 | self.value >> 8;
+  }
+
+  /// OUT 2: only the low 3 bits count
+  pub fn write_offset(&mut self, offset: u8) {
+    self.offset = offset & 0x07;
+  }
+
+  /// IN 3
+  pub fn read_result(&self) -> u8 {
+    (self.value >> (8 - self.offset)) as u8
+  }
+
+  pub fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    write_u16(writer, self.value)?;
+    writer.write_all(&[self.offset])
+  }
+
+  pub fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    self.value = read_u16(reader)?;
+    self.offset = read_u8(reader)?;
+    Ok(())
+  }
+}
+
+#[test]
+fn shift_register_test() {
+  let mut shift_register = ShiftRegister::new();
+  shift_register.write_data(0xab);
+  shift_register.write_data(0xcd);
+  assert_eq!(shift_register.read_result(), 0xcd);
+
+  // 0xcdab shifted left by 4, top 8 bits
+  shift_register.write_offset(4);
+  assert_eq!(shift_register.read_result(), 0xda);
+  shift_register.write_offset(0x0f);  // 7
+  assert_eq!(shift_register.read_result(), 0xd5);
+
+  let mut state = Vec::new();
+  shift_register.save_state(&mut state).unwrap();
+  let mut restored = ShiftRegister::new();
+  restored.load_state(&mut &state[..]).unwrap();
+  assert_eq!(restored.read_result(), 0xd5);
+}
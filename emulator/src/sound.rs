@@ -0,0 +1,272 @@
+//! The sound board of Space Invaders. Each bit of OUT 3 and OUT 5 switches
+//! one analog sound circuit; the emulator logs the edges with their cycle
+//! count and can mix recorded samples of the circuits into a WAV file.
+//!
+//! Samples follow the usual numbering, 0.wav to 9.wav, see `Sound::sample_name`.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use scheduler::CLOCK_HZ;
+
+/// One sound circuit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sound {
+  Ufo,
+  Shot,
+  PlayerDeath,
+  InvaderDeath,
+  ExtraLife,
+  Fleet1,
+  Fleet2,
+  Fleet3,
+  Fleet4,
+  UfoHit,
+}
+
+/// port, bit and sound of every circuit
+const SOUND_BITS: [(u8, u8, Sound); 10] = [
+  (3, 0, Sound::Ufo),
+  (3, 1, Sound::Shot),
+  (3, 2, Sound::PlayerDeath),
+  (3, 3, Sound::InvaderDeath),
+  (3, 4, Sound::ExtraLife),
+  (5, 0, Sound::Fleet1),
+  (5, 1, Sound::Fleet2),
+  (5, 2, Sound::Fleet3),
+  (5, 3, Sound::Fleet4),
+  (5, 4, Sound::UfoHit),
+];
+
+impl Sound {
+  /// File name of the sample of this sound
+  pub fn sample_name(self) -> &'static str {
+    match self {
+      Sound::Ufo => "0.wav",
+      Sound::Shot => "1.wav",
+      Sound::PlayerDeath => "2.wav",
+      Sound::InvaderDeath => "3.wav",
+      Sound::Fleet1 => "4.wav",
+      Sound::Fleet2 => "5.wav",
+      Sound::Fleet3 => "6.wav",
+      Sound::Fleet4 => "7.wav",
+      Sound::UfoHit => "8.wav",
+      Sound::ExtraLife => "9.wav",
+    }
+  }
+
+  /// All sounds
+  pub fn all() -> Vec<Sound> {
+    SOUND_BITS.iter().map(|&(_, _, sound)| sound).collect()
+  }
+
+  /// The UFO hums as long as its bit is set, the others play once per rising edge
+  pub fn loops(self) -> bool {
+    self == Sound::Ufo
+  }
+}
+
+/// A sound bit changed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundEvent {
+  pub cycles: u64,
+  pub sound: Sound,
+  pub on: bool,
+}
+
+impl fmt::Display for SoundEvent {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} {:?} {}", self.cycles, self.sound, if self.on { "on" } else { "off" })
+  }
+}
+
+/// The latches behind OUT 3 and OUT 5 and the edges seen on them
+#[derive(Default)]
+pub struct SoundBoard {
+  port3: u8,
+  port5: u8,
+  cycles: u64,
+  pub events: Vec<SoundEvent>,
+}
+
+impl SoundBoard {
+  pub fn new() -> SoundBoard {
+    SoundBoard::default()
+  }
+
+  /// Tells the board the cycle count, for the timestamps of the events
+  pub fn set_cycles(&mut self, cycles: u64) {
+    self.cycles = cycles;
+  }
+
+  /// OUT 3 or OUT 5
+  pub fn write(&mut self, port: u8, value: u8) {
+    let previous = match port {
+      3 => ::std::mem::replace(&mut self.port3, value),
+      5 => ::std::mem::replace(&mut self.port5, value),
+      _ => return,
+    };
+    for &(_, bit, sound) in SOUND_BITS.iter().filter(|&&(sound_port, _, _)| sound_port == port) {
+      if (previous ^ value) & 1 << bit != 0 {
+        self.events.push(SoundEvent { cycles: self.cycles, sound, on: value & 1 << bit != 0 });
+      }
+    }
+  }
+
+  /// The latches, for save states
+  pub fn ports(&self) -> [u8; 2] {
+    [self.port3, self.port5]
+  }
+
+  pub fn set_ports(&mut self, ports: [u8; 2]) {
+    self.port3 = ports[0];
+    self.port5 = ports[1];
+  }
+}
+
+/// A mono sound recording
+pub struct Sample {
+  pub rate: u32,
+  pub data: Vec<i16>,
+}
+
+impl Sample {
+  /// Reads a PCM WAV file of 8 or 16 bit samples, keeping the first channel
+  pub fn read_wav<R: Read>(reader: &mut R) -> io::Result<Sample> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let invalid = |why: &str| io::Error::new(io::ErrorKind::InvalidData, why.to_string());
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+      return Err(invalid("not a WAV file"));
+    }
+
+    let mut format = None;
+    let mut position = 12;
+    while position + 8 <= bytes.len() {
+      let kind = &bytes[position..position + 4];
+      let size = u32::from_le_bytes([bytes[position + 4], bytes[position + 5], bytes[position + 6], bytes[position + 7]]) as usize;
+      let chunk = &bytes[position + 8..(position + 8 + size).min(bytes.len())];
+      if kind == b"fmt " && chunk.len() >= 16 {
+        let tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+        let channels = u16::from_le_bytes([chunk[2], chunk[3]]) as usize;
+        let rate = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        let bits = u16::from_le_bytes([chunk[14], chunk[15]]);
+        if tag != 1 || channels == 0 || (bits != 8 && bits != 16) {
+          return Err(invalid("only 8 and 16 bit PCM WAV files are supported"));
+        }
+        if rate == 0 {
+          return Err(invalid("WAV sample rate of 0"));
+        }
+        format = Some((channels, rate, bits));
+      }
+      if kind == b"data" {
+        let (channels, rate, bits) = format.ok_or_else(|| invalid("WAV data before its format"))?;
+        let frame_size = channels * bits as usize / 8;
+        let data = chunk.chunks(frame_size).filter(|frame| frame.len() == frame_size).map(|frame| {
+          if bits == 8 { (frame[0] as i16 - 0x80) << 8 } else { i16::from_le_bytes([frame[0], frame[1]]) }
+        }).collect();
+        return Ok(Sample { rate, data });
+      }
+      position += 8 + size + size % 2;
+    }
+    Err(invalid("WAV file without data"))
+  }
+}
+
+/// Rate of the mixed WAV file
+pub const MIX_RATE: u32 = 44100;
+
+/// Mixes the samples of `events` over `cycles` T-states into 16 bit mono at
+/// `MIX_RATE`. `samples` holds a sample per sound of `Sound::all`, sounds
+/// without one stay silent.
+pub fn mix(events: &[SoundEvent], samples: &[(Sound, Sample)], cycles: u64) -> Vec<i16> {
+  let position = |cycles: u64| (cycles as u128 * MIX_RATE as u128 / CLOCK_HZ as u128) as usize;
+  let mut mixed = vec![0i32; position(cycles)];
+
+  for (index, event) in events.iter().enumerate().filter(|&(_, event)| event.on) {
+    let sample = match samples.iter().find(|&&(sound, _)| sound == event.sound) {
+      Some((_, sample)) if !sample.data.is_empty() => sample,
+      _ => continue,
+    };
+    let start = position(event.cycles);
+    let end = if event.sound.loops() {
+      let off = events[index + 1..].iter().find(|later| later.sound == event.sound && !later.on);
+      off.map_or(mixed.len(), |off| position(off.cycles))
+    } else {
+      start + (sample.data.len() as u64 * MIX_RATE as u64 / sample.rate as u64) as usize
+    };
+    for at in start..end.min(mixed.len()) {
+      // nearest neighbour resampling is good enough to tell the sounds apart
+      let source = ((at - start) as u64 * sample.rate as u64 / MIX_RATE as u64) as usize % sample.data.len();
+      mixed[at] += sample.data[source] as i32;
+    }
+  }
+  mixed.iter().map(|&value| value.max(i16::MIN as i32).min(i16::MAX as i32) as i16).collect()
+}
+
+/// Writes 16 bit mono PCM at `MIX_RATE` as a WAV file
+pub fn write_wav<W: Write>(writer: &mut W, data: &[i16]) -> io::Result<()> {
+  let data_size = data.len() as u32 * 2;
+  writer.write_all(b"RIFF")?;
+  writer.write_all(&(36 + data_size).to_le_bytes())?;
+  writer.write_all(b"WAVEfmt ")?;
+  writer.write_all(&16u32.to_le_bytes())?;
+  writer.write_all(&1u16.to_le_bytes())?;             // PCM
+  writer.write_all(&1u16.to_le_bytes())?;             // mono
+  writer.write_all(&MIX_RATE.to_le_bytes())?;
+  writer.write_all(&(MIX_RATE * 2).to_le_bytes())?;   // bytes per second
+  writer.write_all(&2u16.to_le_bytes())?;             // bytes per frame
+  writer.write_all(&16u16.to_le_bytes())?;
+  writer.write_all(b"data")?;
+  writer.write_all(&data_size.to_le_bytes())?;
+  for value in data {
+    writer.write_all(&value.to_le_bytes())?;
+  }
+  Ok(())
+}
+
+#[test]
+fn sound_board_test() {
+  let mut sound_board = SoundBoard::new();
+  sound_board.set_cycles(100);
+  sound_board.write(3, 0x22);  // shot, amplifier on
+  sound_board.set_cycles(200);
+  sound_board.write(3, 0x20);
+  sound_board.write(5, 0x01);
+  sound_board.write(5, 0x01);  // no edge
+  assert_eq!(sound_board.events, vec![
+    SoundEvent { cycles: 100, sound: Sound::Shot, on: true },
+    SoundEvent { cycles: 200, sound: Sound::Shot, on: false },
+    SoundEvent { cycles: 200, sound: Sound::Fleet1, on: true },
+  ]);
+  assert_eq!(sound_board.events[0].to_string(), "100 Shot on");
+}
+
+#[test]
+fn mix_test() {
+  let mut wav = Vec::new();
+  write_wav(&mut wav, &[1000, -1000, 1000, -1000]).unwrap();
+  let sample = Sample::read_wav(&mut &wav[..]).unwrap();
+  assert_eq!(sample.rate, MIX_RATE);
+  assert_eq!(sample.data, vec![1000, -1000, 1000, -1000]);
+  let mut silent = wav.clone();
+  silent[24..28].copy_from_slice(&[0, 0, 0, 0]);
+  assert_eq!(Sample::read_wav(&mut &silent[..]).err().unwrap().to_string(), "WAV sample rate of 0");
+
+  let events = [
+    SoundEvent { cycles: 0, sound: Sound::Shot, on: true },
+    SoundEvent { cycles: 1000, sound: Sound::Ufo, on: true },
+    SoundEvent { cycles: 2000, sound: Sound::Ufo, on: false },
+    SoundEvent { cycles: 3000, sound: Sound::Fleet1, on: true },
+  ];
+  let ufo = Sample { rate: MIX_RATE, data: vec![7] };
+  let shot = Sample { rate: MIX_RATE, data: sample.data };
+  let mixed = mix(&events, &[(Sound::Shot, shot), (Sound::Ufo, ufo)], 4000);
+  // 4000 T-states at 2 MHz are 88 samples at 44.1 kHz
+  assert_eq!(mixed.len(), 88);
+  assert_eq!(&mixed[..5], &[1000, -1000, 1000, -1000, 0]);
+  assert_eq!(mixed[22], 7);
+  assert_eq!(mixed[43], 7);
+  assert_eq!(mixed[44], 0);
+  assert!(mixed[45..].iter().all(|&value| value == 0));
+}
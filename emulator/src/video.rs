@@ -0,0 +1,181 @@
+//! The Space Invaders display. Video RAM at 0x2400-0x3fff holds 224 lines of
+//! 256 pixels, one bit each with the lowest bit leftmost. The monitor is
+//! mounted turned by 90 degrees, so the player sees 224 columns of 256 rows:
+//! VRAM line n is screen column n and the first pixel of a line is the bottom.
+
+use std::io::{self, Write};
+
+use i8080::Bus;
+
+use crc32::crc32;
+
+pub const WIDTH: usize = 224;
+pub const HEIGHT: usize = 256;
+pub const VRAM_START: u16 = 0x2400;
+
+/// One frame as the player sees it, row major from the top left, one byte
+/// per pixel: 0x00 dark, 0xff lit
+pub struct Framebuffer {
+  pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+  pub fn new() -> Framebuffer {
+    Framebuffer { pixels: vec![0; WIDTH * HEIGHT] }
+  }
+
+  /// Redraws the frame from the video RAM behind `bus`
+  pub fn render<B: Bus>(&mut self, bus: &B) {
+    for column in 0..WIDTH {
+      for byte in 0..HEIGHT / 8 {
+        let bits = bus.read(VRAM_START + (column * HEIGHT / 8 + byte) as u16);
+        for bit in 0..8 {
+          let row = HEIGHT - 1 - (byte * 8 + bit);
+          self.pixels[row * WIDTH + column] = if bits & 1 << bit != 0 { 0xff } else { 0x00 };
+        }
+      }
+    }
+  }
+
+  /// All pixels, row major from the top left
+  pub fn pixels(&self) -> &[u8] {
+    &self.pixels
+  }
+
+  pub fn pixel(&self, x: usize, y: usize) -> bool {
+    self.pixels[y * WIDTH + x] != 0
+  }
+
+  /// Writes the frame as binary PPM, lit pixels white
+  pub fn write_ppm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", WIDTH, HEIGHT)?;
+    let mut rgb = Vec::with_capacity(self.pixels.len() * 3);
+    for &pixel in &self.pixels {
+      rgb.extend_from_slice(&[pixel, pixel, pixel]);
+    }
+    writer.write_all(&rgb)
+  }
+
+  /// Writes the frame as 8 bit greyscale PNG, stored without compression
+  pub fn write_png<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+    header.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);  // 8 bit greyscale, no interlace
+    write_chunk(writer, b"IHDR", &header)?;
+
+    // every row starts with filter type 0
+    let mut scanlines = Vec::with_capacity((WIDTH + 1) * HEIGHT);
+    for row in self.pixels.chunks(WIDTH) {
+      scanlines.push(0);
+      scanlines.extend_from_slice(row);
+    }
+
+    // zlib stream of stored deflate blocks
+    let mut data = vec![0x78, 0x01];
+    let mut blocks = scanlines.chunks(0xffff).peekable();
+    while let Some(block) = blocks.next() {
+      let last = blocks.peek().is_none();
+      data.push(last as u8);
+      data.extend_from_slice(&(block.len() as u16).to_le_bytes());
+      data.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+      data.extend_from_slice(block);
+    }
+    data.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+    write_chunk(writer, b"IDAT", &data)?;
+
+    write_chunk(writer, b"IEND", &[])
+  }
+}
+
+impl Default for Framebuffer {
+  fn default() -> Framebuffer {
+    Framebuffer::new()
+  }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+  writer.write_all(&(data.len() as u32).to_be_bytes())?;
+  writer.write_all(kind)?;
+  writer.write_all(data)?;
+  let mut checked = kind.to_vec();
+  checked.extend_from_slice(data);
+  writer.write_all(&crc32(&checked).to_be_bytes())
+}
+
+fn adler32(data: &[u8]) -> u32 {
+  let (mut a, mut b) = (1u32, 0u32);
+  for &byte in data {
+    a = (a + byte as u32) % 65521;
+    b = (b + a) % 65521;
+  }
+  b << 16 | a
+}
+
+#[test]
+fn render_test() {
+  use i8080::FlatMemory;
+
+  let mut memory = FlatMemory::new();
+  // first bit of the first line: bottom left; last bit of the last line: top right
+  memory[0x2400] = 0x01;
+  memory[0x3fff] = 0x80;
+  // a line along the top of the tenth column
+  memory[0x2400 + 10 * 32 + 31] = 0xff;
+
+  let mut framebuffer = Framebuffer::new();
+  framebuffer.render(&memory);
+  assert!(framebuffer.pixel(0, HEIGHT - 1));
+  assert!(framebuffer.pixel(WIDTH - 1, 0));
+  assert!((0..8).all(|y| framebuffer.pixel(10, y)));
+  assert_eq!(framebuffer.pixels().iter().filter(|&&pixel| pixel != 0).count(), 10);
+
+  let mut ppm = Vec::new();
+  framebuffer.write_ppm(&mut ppm).unwrap();
+  assert!(ppm.starts_with(b"P6\n224 256\n255\n"));
+  assert_eq!(ppm.len(), 15 + WIDTH * HEIGHT * 3);
+  assert_eq!(ppm[15..18], [0x00, 0x00, 0x00]);
+  assert_eq!(ppm[15 + (WIDTH - 1) * 3..15 + WIDTH * 3], [0xff, 0xff, 0xff]);
+
+  let mut png = Vec::new();
+  framebuffer.write_png(&mut png).unwrap();
+  assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+  let word = |at: usize| u32::from_be_bytes([png[at], png[at + 1], png[at + 2], png[at + 3]]);
+  assert_eq!(&png[12..16], b"IHDR");
+  assert_eq!((word(16), word(20)), (WIDTH as u32, HEIGHT as u32));
+
+  // collect the IDAT data, checking the CRC of every chunk
+  let (mut position, mut zlib) = (8, Vec::new());
+  while position < png.len() {
+    let length = word(position) as usize;
+    let chunk = &png[position + 4..position + 8 + length];
+    assert_eq!(crc32(chunk), word(position + 8 + length), "CRC of {}", String::from_utf8_lossy(&chunk[..4]));
+    if &chunk[..4] == b"IDAT" {
+      zlib.extend_from_slice(&chunk[4..]);
+    }
+    position += 12 + length;
+  }
+
+  // inflate the stored blocks
+  let (mut at, mut scanlines) = (2, Vec::new());
+  loop {
+    let last = zlib[at] & 0x01 != 0;
+    assert_eq!(zlib[at] & 0x06, 0, "only stored blocks");
+    let length = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]);
+    assert_eq!(!length, u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]));
+    scanlines.extend_from_slice(&zlib[at + 5..at + 5 + length as usize]);
+    at += 5 + length as usize;
+    if last {
+      break;
+    }
+  }
+  assert_eq!(u32::from_be_bytes([zlib[at], zlib[at + 1], zlib[at + 2], zlib[at + 3]]), adler32(&scanlines));
+  assert_eq!(scanlines.len(), (WIDTH + 1) * HEIGHT);
+  let png_pixel = |x: usize, y: usize| scanlines[y * (WIDTH + 1) + 1 + x];
+  assert_eq!(png_pixel(WIDTH - 1, 0), 0xff);
+  assert_eq!(png_pixel(0, HEIGHT - 1), 0xff);
+  assert_eq!(png_pixel(0, 0), 0x00);
+  assert_eq!(png_pixel(10, 8), 0x00);
+}
@@ -0,0 +1,114 @@
+//! The address bus: how the processor reaches memory
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::{Deref, DerefMut};
+
+/// Everything the processor reaches through its address bus. A machine
+/// implements this to lay out ROM, RAM, mirrors and memory mapped devices.
+pub trait Bus {
+  /// Reads the byte at `address`, unmapped addresses usually read as 0xff
+  fn read(&self, address: u16) -> u8;
+
+  /// Writes `value` to `address`
+  fn write(&mut self, address: u16, value: u8);
+
+  /// Takes the first fault latched by an access since the last call, if any.
+  /// `emulate` checks this after every instruction and reports it as an error.
+  fn take_fault(&mut self) -> Option<BusFault> {
+    None
+  }
+
+  /// The bank selected in each banked window, for `Rewind` to undo bank
+  /// switches. Buses without banks have none.
+  fn banks(&self) -> Vec<u8> {
+    Vec::new()
+  }
+
+  /// Selects the banks `banks` returned
+  fn set_banks(&mut self, _banks: &[u8]) {
+  }
+
+  /// Writes the contents of RAM and the state of memory mapped devices for a
+  /// save state. ROM the machine loads itself can be left out.
+  fn save_state(&self, _writer: &mut dyn Write) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// Restores what `save_state` wrote
+  fn load_state(&mut self, _reader: &mut dyn Read) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// An access the bus could not serve, latched by the `Bus` until `take_fault`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BusFault {
+  /// Read from an address nothing is mapped to
+  UnmappedRead(u16),
+  /// Write to an address nothing is mapped to
+  UnmappedWrite(u16),
+  /// Write to read only memory
+  WriteProtected(u16),
+}
+
+impl fmt::Display for BusFault {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      BusFault::UnmappedRead(address) => write!(f, "read from unmapped address {:04x}", address),
+      BusFault::UnmappedWrite(address) => write!(f, "write to unmapped address {:04x}", address),
+      BusFault::WriteProtected(address) => write!(f, "write to read only address {:04x}", address),
+    }
+  }
+}
+
+/// 64 KiB of plain RAM covering the whole address space, the default `Bus`.
+/// Derefs to the underlying bytes.
+pub struct FlatMemory {
+  bytes: Vec<u8>,
+}
+
+impl FlatMemory {
+  /// Cleared RAM
+  pub fn new() -> FlatMemory {
+    FlatMemory { bytes: vec![0; 0x10000] }
+  }
+}
+
+impl Default for FlatMemory {
+  fn default() -> FlatMemory {
+    FlatMemory::new()
+  }
+}
+
+impl Bus for FlatMemory {
+  fn read(&self, address: u16) -> u8 {
+    self.bytes[address as usize]
+  }
+
+  fn write(&mut self, address: u16, value: u8) {
+    self.bytes[address as usize] = value;
+  }
+
+  fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    writer.write_all(&self.bytes)
+  }
+
+  fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    reader.read_exact(&mut self.bytes)
+  }
+}
+
+impl Deref for FlatMemory {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    &self.bytes
+  }
+}
+
+impl DerefMut for FlatMemory {
+  fn deref_mut(&mut self) -> &mut [u8] {
+    &mut self.bytes
+  }
+}
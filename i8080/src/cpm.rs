@@ -0,0 +1,341 @@
+//! Just enough CP/M to run .COM programs: the program is loaded at 0x0100,
+//! CALL 5 goes to the BDOS and JMP 0 (warm boot) ends the run.
+//!
+//! `Cpm::step` wraps `emulate` and handles the BDOS itself when the program
+//! reaches address 5, so no CP/M code runs on the emulated processor. Only the
+//! console functions are there, no disks:
+//!
+//! | C  | function                                              |
+//! |----|-------------------------------------------------------|
+//! | 0  | system reset, ends the run like JMP 0                 |
+//! | 1  | console input, A = the byte                           |
+//! | 2  | console output of E                                   |
+//! | 6  | direct console I/O: E = ff input, fe status, else output E |
+//! | 9  | print the string at DE up to `$`, at most 64 KiB      |
+//! | 10 | read a line ended by CR or LF into the buffer at DE   |
+//! | 11 | console status, A = ff if a byte is waiting           |
+//! | 12 | version, HL = 0022 for CP/M 2.2                       |
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use bus::Bus;
+use cpu::{CpuState, emulate};
+use error::EmulateError;
+use io::Io;
+
+/// Warm boot, jumping here ends the program
+pub const BOOT: u16 = 0x0000;
+
+/// BDOS entry point the program calls
+pub const BDOS: u16 = 0x0005;
+
+/// Transient program area, .COM files are loaded and started here
+pub const TPA: u16 = 0x0100;
+
+/// Where the JMP at 5 points, programs read it to find the top of their memory
+const BDOS_BASE: u16 = 0xfe06;
+
+/// BIOS warm boot entry the JMP at 0 points to
+const BIOS_WBOOT: u16 = 0xff03;
+
+/// The terminal of the CP/M machine
+pub trait Console {
+  /// Next byte typed, waiting for one if needed; None once the input has ended
+  fn read(&mut self) -> Option<u8>;
+
+  /// True if `read` returns a byte without waiting
+  fn ready(&mut self) -> bool;
+
+  /// Prints `byte`
+  fn write(&mut self, byte: u8);
+}
+
+/// A BDOS call `Cpm` could not serve as asked, see `Cpm::error`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BdosError {
+  /// The function in C is not implemented, it returned 0 in A and HL
+  Unsupported(u8),
+  /// Function 9 found no `$` after the string at this address and stopped
+  /// once it had printed all of memory
+  Unterminated(u16),
+}
+
+impl fmt::Display for BdosError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      BdosError::Unsupported(function) => write!(f, "BDOS function {} is not supported", function),
+      BdosError::Unterminated(address) => write!(f, "no $ ends the string printed from {:04x}", address),
+    }
+  }
+}
+
+impl Error for BdosError {}
+
+/// A console fed from memory that collects what is printed, for tests and scripted runs
+#[derive(Debug, Default)]
+pub struct BufferConsole {
+  pub input: VecDeque<u8>,
+  pub output: Vec<u8>,
+}
+
+impl BufferConsole {
+  pub fn new(input: &[u8]) -> BufferConsole {
+    BufferConsole { input: input.iter().cloned().collect(), output: Vec::new() }
+  }
+}
+
+impl Console for BufferConsole {
+  fn read(&mut self) -> Option<u8> {
+    self.input.pop_front()
+  }
+
+  fn ready(&mut self) -> bool {
+    !self.input.is_empty()
+  }
+
+  fn write(&mut self, byte: u8) {
+    self.output.push(byte);
+  }
+}
+
+/// The CP/M environment around a program, see the module docs
+pub struct Cpm<C> {
+  pub console: C,
+  /// Echo what functions 1 and 10 read, as CP/M does on a raw terminal.
+  /// Off by default since the host terminal echoes already.
+  pub echo: bool,
+  exited: bool,
+  error: Option<BdosError>,
+  /// the last line read ended on CR, a LF right after it belongs to it
+  after_return: bool,
+}
+
+impl<C: Console> Cpm<C> {
+  pub fn new(console: C) -> Cpm<C> {
+    Cpm { console, echo: false, exited: false, error: None, after_return: false }
+  }
+
+  /// Sets up page zero, loads `program` at 0x0100 and points pc at it, with
+  /// the return address 0 on the stack like the CCP leaves it
+  pub fn load<M: Bus, P: Io>(&mut self, cpu_state: &mut CpuState<M, P>, program: &[u8]) -> io::Result<()> {
+    // the stack starts right below the BDOS with the return address on it
+    if program.len() > (BDOS_BASE - 2 - TPA) as usize {
+      return Err(io::Error::new(io::ErrorKind::InvalidInput,
+        format!("program of {} bytes does not fit below the stack at {:04x}", program.len(), BDOS_BASE - 2)));
+    }
+    let memory = &mut cpu_state.memory;
+    for (address, &byte) in [0xc3, BIOS_WBOOT as u8, (BIOS_WBOOT >> 8) as u8].iter().enumerate() {
+      memory.write(BOOT + address as u16, byte);
+    }
+    for (address, &byte) in [0xc3, BDOS_BASE as u8, (BDOS_BASE >> 8) as u8].iter().enumerate() {
+      memory.write(BDOS + address as u16, byte);
+    }
+    // a RET in case anything jumps to the BDOS base instead of 5
+    memory.write(BDOS_BASE, 0xc9);
+    for (offset, &byte) in program.iter().enumerate() {
+      memory.write(TPA + offset as u16, byte);
+    }
+
+    cpu_state.sp = BDOS_BASE - 2;
+    memory.write(cpu_state.sp, 0x00);
+    memory.write(cpu_state.sp + 1, 0x00);
+    cpu_state.pc = TPA;
+    self.exited = false;
+    Ok(())
+  }
+
+  /// Executes one instruction, or the BDOS call or warm boot at pc. Returns
+  /// the T-states taken, a BDOS call counts as the RET that ends it.
+  pub fn step<M: Bus, P: Io>(&mut self, cpu_state: &mut CpuState<M, P>) -> Result<u32, EmulateError> {
+    match cpu_state.pc {
+      BOOT => {
+        self.exited = true;
+        Ok(0)
+      },
+      BDOS => {
+        self.bdos(cpu_state);
+        let low = cpu_state.memory.read(cpu_state.sp) as u16;
+        let high = cpu_state.memory.read(cpu_state.sp.wrapping_add(1)) as u16;
+        cpu_state.sp = cpu_state.sp.wrapping_add(2);
+        cpu_state.pc = high << 8 | low;
+        cpu_state.cycles += 10;
+        Ok(10)
+      },
+      _ => emulate(cpu_state),
+    }
+  }
+
+  /// True once the program warm booted or called function 0
+  pub fn exited(&self) -> bool {
+    self.exited
+  }
+
+  /// The first BDOS call that went wrong. The program keeps running, so
+  /// check this once it exited.
+  pub fn error(&self) -> Option<BdosError> {
+    self.error
+  }
+
+  fn bdos<M: Bus, P: Io>(&mut self, cpu_state: &mut CpuState<M, P>) {
+    let de = (cpu_state.d as u16) << 8 | cpu_state.e as u16;
+    let result: u16 = match cpu_state.c {
+      0 => {
+        self.exited = true;
+        0
+      },
+      1 => {
+        // ^Z once the input has ended, as when reading a file
+        let byte = self.console.read().unwrap_or(0x1a);
+        if self.echo {
+          self.console.write(byte);
+        }
+        byte as u16
+      },
+      2 => {
+        self.console.write(cpu_state.e);
+        0
+      },
+      6 => match cpu_state.e {
+        0xff => if self.console.ready() { self.console.read().unwrap_or(0) as u16 } else { 0 },
+        0xfe => if self.console.ready() { 0xff } else { 0 },
+        byte => {
+          self.console.write(byte);
+          0
+        },
+      },
+      9 => {
+        let terminated = (0..=0xffff).any(|offset| {
+          let byte = cpu_state.memory.read(de.wrapping_add(offset));
+          if byte != b'$' {
+            self.console.write(byte);
+          }
+          byte == b'$'
+        });
+        if !terminated {
+          self.error.get_or_insert(BdosError::Unterminated(de));
+        }
+        0
+      },
+      10 => {
+        self.read_line(cpu_state, de);
+        0
+      },
+      11 => if self.console.ready() { 0xff } else { 0 },
+      12 => 0x0022,
+      function => {
+        self.error.get_or_insert(BdosError::Unsupported(function));
+        0
+      },
+    };
+    // results come in A and L, 16 bit ones in HL with B = H
+    cpu_state.a = result as u8;
+    cpu_state.l = result as u8;
+    cpu_state.h = (result >> 8) as u8;
+    cpu_state.b = cpu_state.h;
+  }
+
+  /// function 10: the buffer holds the maximum length, then the length read, then the bytes
+  fn read_line<M: Bus, P: Io>(&mut self, cpu_state: &mut CpuState<M, P>, buffer: u16) {
+    let maximum = cpu_state.memory.read(buffer);
+    let mut line: Vec<u8> = Vec::new();
+    let mut after_return = self.after_return;
+    self.after_return = false;
+    while line.len() < maximum as usize {
+      let byte = self.console.read();
+      if after_return && byte == Some(b'\n') {
+        after_return = false;
+        continue;
+      }
+      after_return = false;
+      match byte {
+        None | Some(b'\n') => break,
+        Some(b'\r') => {
+          self.after_return = true;
+          break;
+        },
+        Some(0x08) | Some(0x7f) => {
+          line.pop();
+        },
+        Some(byte) => line.push(byte),
+      }
+    }
+    if self.echo {
+      for &byte in line.iter().chain(b"\r\n") {
+        self.console.write(byte);
+      }
+    }
+    cpu_state.memory.write(buffer.wrapping_add(1), line.len() as u8);
+    for (offset, &byte) in line.iter().enumerate() {
+      cpu_state.memory.write(buffer.wrapping_add(2 + offset as u16), byte);
+    }
+  }
+}
+
+#[test]
+fn cpm_test() {
+  use cpu::init_cpu;
+
+  // MVI C,9; LXI D,prompt; CALL 5; MVI C,10; LXI D,buffer; CALL 5;
+  // MVI C,2; LDA buffer+2; MOV E,A; CALL 5; MVI C,11; CALL 5; STA status; RET
+  let program = [
+    0x0e, 0x09, 0x11, 0x30, 0x01, 0xcd, 0x05, 0x00,
+    0x0e, 0x0a, 0x11, 0x40, 0x01, 0xcd, 0x05, 0x00,
+    0x0e, 0x02, 0x3a, 0x42, 0x01, 0x5f, 0xcd, 0x05, 0x00,
+    0x0e, 0x0b, 0xcd, 0x05, 0x00, 0x32, 0x50, 0x01, 0xc9,
+  ];
+  let mut com = program.to_vec();
+  com.resize(0x30, 0);
+  com.extend_from_slice(b"name? $");
+  com.resize(0x40, 0);
+  com.push(4);
+
+  let mut cpu_state = init_cpu();
+  let mut cpm = Cpm::new(BufferConsole::new(b"ab\x08cdef\nx"));
+  cpm.load(&mut cpu_state, &com).unwrap();
+  assert_eq!(cpu_state.memory[0x0006..0x0008], [0x06, 0xfe]);
+  while !cpm.exited() {
+    cpm.step(&mut cpu_state).unwrap();
+  }
+
+  assert_eq!(cpm.console.output, b"name? a");
+  // the backspace removed b, the line ends after the maximum of 4 bytes
+  assert_eq!(cpu_state.memory[0x0141..0x0146], [4, b'a', b'c', b'd', b'e']);
+  assert_eq!(cpu_state.memory[0x0150], 0xff);
+  assert_eq!(cpm.error(), None);
+  assert!(cpu_state.cycles > 0);
+
+  // MVI C,10; LXI D,buffer; CALL 5; MVI C,10; LXI D,buffer+8; CALL 5; RET
+  let mut com = vec![0x0e, 0x0a, 0x11, 0x40, 0x01, 0xcd, 0x05, 0x00, 0x0e, 0x0a, 0x11, 0x48, 0x01, 0xcd, 0x05, 0x00, 0xc9];
+  com.resize(0x40, 0);
+  com.push(4);
+  com.resize(0x48, 0);
+  com.push(4);
+  let mut cpu_state = init_cpu();
+  let mut cpm = Cpm::new(BufferConsole::new(b"ab\r\ncd"));
+  cpm.load(&mut cpu_state, &com).unwrap();
+  while !cpm.exited() {
+    cpm.step(&mut cpu_state).unwrap();
+  }
+  // CR ends the first line and the LF after it is not an empty second line
+  assert_eq!(cpu_state.memory[0x0141..0x0144], [2, b'a', b'b']);
+  assert_eq!(cpu_state.memory[0x0149..0x014c], [2, b'c', b'd']);
+
+  // the program must leave room for the return address below the BDOS
+  let mut cpu_state = init_cpu();
+  assert!(cpm.load(&mut cpu_state, &vec![0; (BDOS_BASE - 2 - TPA) as usize]).is_ok());
+  assert!(cpm.load(&mut cpu_state, &vec![0; (BDOS_BASE - 1 - TPA) as usize]).is_err());
+
+  // MVI C,9; LXI D,0200; CALL 5; MVI C,99; CALL 5; RET with no $ in memory
+  let mut cpu_state = init_cpu();
+  let mut cpm = Cpm::new(BufferConsole::default());
+  cpm.load(&mut cpu_state, &[0x0e, 0x09, 0x11, 0x00, 0x02, 0xcd, 0x05, 0x00, 0x0e, 0x63, 0xcd, 0x05, 0x00, 0xc9]).unwrap();
+  while !cpm.exited() {
+    cpm.step(&mut cpu_state).unwrap();
+  }
+  assert_eq!(cpm.console.output.len(), 0x10000);
+  assert_eq!(cpm.error(), Some(BdosError::Unterminated(0x0200)));
+  assert_eq!(cpm.error().unwrap().to_string(), "no $ ends the string printed from 0200");
+}
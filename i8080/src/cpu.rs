@@ -0,0 +1,1256 @@
+//! The 8080 processor: registers, flags and the instruction interpreter
+
+use std::fmt;
+
+use bus::{Bus, FlatMemory};
+use error::{EmulateError, ErrorKind};
+use io::{Io, NullIo};
+use opcodes::OPCODES;
+
+/// The flags of the 8080, set by the arithmetic and logic instructions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConditionCode {
+    /// Zero: set if the result is zero
+    pub z: bool,
+    /// Sign: set if the result is negative
+    pub s: bool,
+    /// Parity: set if the number of 1 bits in the result is even
+    pub p: bool,
+    /// Carry: set if the last addition operation resulted in a carry, or
+    /// if the last subtraction operation required a borrow
+    pub cy: bool,
+    /// Auxiliary carry: set on a carry out of bit 3, used by DAA
+    pub ac: bool,
+}
+
+/// Registers, flags, memory, port devices and interrupt state of one 8080
+pub struct CpuState<M = FlatMemory, P = NullIo> {
+    /// Register A: primary 8-bit accumulator
+    pub a: u8,
+    /// Register B: either 8-bit single or B (BC) 16-bit register
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    /// Stack pointer, the stack grows down
+    pub sp: u16,
+    /// Program counter, address of the next instruction
+    pub pc: u16,
+    /// Everything behind the address bus, see `Bus`
+    pub memory: M,
+    /// Devices behind the IN and OUT ports, see `Io`
+    pub io: P,
+    pub cc: ConditionCode,
+    /// 1 if the processor accepts interrupts, set by EI and cleared by DI
+    pub int_enable: u8,
+    /// set by EI, interrupts are accepted only after the instruction following EI
+    pub int_delay: bool,
+    /// instruction the interrupting device puts on the data bus, pending until accepted
+    pub interrupt_request: Option<[u8; 3]>,
+    /// set by HLT, the processor stops until an interrupt is accepted
+    pub halted: bool,
+    /// T-states executed since reset
+    pub cycles: u64,
+    /// Report the undocumented opcode aliases as `ErrorKind::IllegalOpcode`
+    /// instead of executing them, helps to catch runaway code
+    pub strict: bool,
+}
+
+/// A copy of the registers, flags and interrupt state, without memory and devices
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Registers {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub cc: ConditionCode,
+    pub int_enable: u8,
+    pub cycles: u64,
+}
+
+impl fmt::Display for Registers {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "A:{:02x} B:{:02x} C:{:02x} D:{:02x} E:{:02x} H:{:02x} L:{:02x} SP:{:04x} PC:{:04x} z:{:?} s:{:?} p:{:?} cy:{:?} ac:{:?}",
+      self.a, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc,
+      self.cc.z, self.cc.s, self.cc.p, self.cc.cy, self.cc.ac)
+  }
+}
+
+/// A processor in its reset state with 64 KiB of cleared RAM and no port devices
+pub fn init_cpu() -> CpuState {
+  init_cpu_with(FlatMemory::new(), NullIo)
+}
+
+/// A processor in its reset state attached to `memory` and the port devices `io`
+pub fn init_cpu_with<M: Bus, P: Io>(memory: M, io: P) -> CpuState<M, P> {
+
+  let con_code = ConditionCode{ z:false, s:false, p:false, cy:false, ac:false, };
+
+  CpuState{
+    a:0x00,
+    b:0x00,
+    c:0x00,
+    d:0x00,
+    e:0x00,
+    h:0x00,
+    l:0x00,
+    sp:0x0000,
+    pc:0x0000,
+    memory,
+    io,
+    cc: con_code,
+    int_enable: 0,
+    int_delay: false,
+    interrupt_request: None,
+    halted: false,
+    cycles: 0,
+    strict: false,
+  }
+}
+
+/// Snapshot of the registers of `cpu_state`
+pub fn registers<M: Bus, P: Io>(cpu_state: &CpuState<M, P>) -> Registers {
+  Registers {
+    a: cpu_state.a,
+    b: cpu_state.b,
+    c: cpu_state.c,
+    d: cpu_state.d,
+    e: cpu_state.e,
+    h: cpu_state.h,
+    l: cpu_state.l,
+    sp: cpu_state.sp,
+    pc: cpu_state.pc,
+    cc: cpu_state.cc,
+    int_enable: cpu_state.int_enable,
+    cycles: cpu_state.cycles,
+  }
+}
+
+/// Executes one instruction and returns the number of T-states it took.
+///
+/// Accepts a pending interrupt instead if interrupts are enabled. While halted
+/// nothing is executed and 4 T-states pass per call, unless interrupts are
+/// disabled too, then the processor is stuck and `ErrorKind::Halted` is returned.
+pub fn emulate<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>) -> Result<u32, EmulateError> {
+
+  let int_delay = cpu_state.int_delay;
+  cpu_state.int_delay = false;
+
+  // faults latched outside of an instruction, e.g. by a debugger reading memory
+  cpu_state.memory.take_fault();
+
+  if cpu_state.int_enable == 1 && !int_delay {
+    if let Some(instruction) = cpu_state.interrupt_request.take() {
+      // the interrupting device supplies the instruction and its operands, pc is not advanced.
+      // rewind pc by the operand bytes the opcode arm will skip, so RST and CALL push the
+      // address of the interrupted instruction
+      let pc = cpu_state.pc;
+      cpu_state.int_enable = 0;
+      cpu_state.halted = false;
+      cpu_state.pc = cpu_state.pc.wrapping_sub(OPCODES[instruction[0] as usize].size as u16 - 1);
+      let operation_cycles = execute(cpu_state, instruction[0], instruction[1], instruction[2]);
+      return check_bus(cpu_state, pc, operation_cycles);
+    }
+  }
+
+  if cpu_state.halted {
+    if cpu_state.int_enable == 0 {
+      return Err(error(cpu_state, ErrorKind::Halted));
+    }
+
+    // waiting for an interrupt, time passes without executing anything
+    cpu_state.cycles += 4;
+    return Ok(4);
+  }
+
+  let pc = cpu_state.pc;
+  let operation_code = cpu_state.memory.read(pc);
+  if cpu_state.strict && OPCODES[operation_code as usize].undocumented {
+    return Err(error(cpu_state, ErrorKind::IllegalOpcode(operation_code)));
+  }
+
+  // only fetch the operands the instruction has, the bytes behind it may not be mapped
+  let operation_size = OPCODES[operation_code as usize].size as u16;
+  let operation_arg1 = if operation_size > 1 { cpu_state.memory.read(pc.wrapping_add(1)) } else { 0 };
+  let operation_arg2 = if operation_size > 2 { cpu_state.memory.read(pc.wrapping_add(2)) } else { 0 };
+
+  cpu_state.pc = cpu_state.pc.wrapping_add(1);
+
+  let operation_cycles = execute(cpu_state, operation_code, operation_arg1, operation_arg2);
+  check_bus(cpu_state, pc, operation_cycles)
+}
+
+/// the error `kind` with a snapshot of the registers
+fn error<M: Bus, P: Io>(cpu_state: &CpuState<M, P>, kind: ErrorKind) -> EmulateError {
+  EmulateError { kind, registers: registers(cpu_state) }
+}
+
+/// reports a fault the bus latched while the instruction at `pc` executed
+fn check_bus<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, pc: u16, operation_cycles: u32) -> Result<u32, EmulateError> {
+  match cpu_state.memory.take_fault() {
+    Some(fault) => Err(error(cpu_state, ErrorKind::BusFault { pc, fault })),
+    None => Ok(operation_cycles),
+  }
+}
+
+/// Asserts the interrupt line with `instruction` on the data bus, usually a RST opcode
+/// followed by two unused bytes. The request stays pending until interrupts are enabled.
+pub fn interrupt<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, instruction: [u8; 3]) {
+  cpu_state.interrupt_request = Some(instruction);
+}
+
+/// Asserts the interrupt line with RST `n` on the data bus
+pub fn interrupt_rst<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, n: u8) {
+  interrupt(cpu_state, [0xc7 | (n & 0x07) << 3, 0x00, 0x00]);
+}
+
+/// executes one decoded instruction, pc already points behind the opcode. The
+/// T-states come from `OPCODES`, the arms only tell whether a condition was taken
+fn execute<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, operation_code: u8, operation_arg1: u8, operation_arg2: u8) -> u32 {
+
+  let operation_address: u16 = (operation_arg2 as u16) << 8 | (operation_arg1 as u16);
+  // set by conditional calls and returns that branch
+  let mut taken = false;
+
+  match operation_code {
+
+    //NOP ;4c ;os=1byte
+    0x00 => {},
+
+    //LXI B, u16   load immediate register pair BC   10c; os=3
+    0x01 => {
+      cpu_state.c = operation_arg1;
+      cpu_state.b = operation_arg2;
+      cpu_state.pc = cpu_state.pc.wrapping_add(2);
+    },
+
+    //STAX B  store A indirect to the address in BC; 7c; os=1byte
+    0x02 => { let offset = bc(cpu_state); cpu_state.memory.write(offset, cpu_state.a); },
+
+    //INX B ;5c; os=1byte increment register BC
+    0x03 => { let res = bc(cpu_state).wrapping_add(1); set_bc(cpu_state, res); },
+
+    //INR B ;5c; os=1byte increment register B
+    0x04 => { cpu_state.b = inr(cpu_state, cpu_state.b); },
+
+    //DRC B ; 5c; os=1byte decrement register B
+    0x05 => { cpu_state.b = dcr(cpu_state, cpu_state.b); }
+
+    //MVI B, u8  Move immediate value to B ;7c; os=2byte
+    0x06 => { cpu_state.b = operation_arg1; cpu_state.pc = cpu_state.pc.wrapping_add(1); }
+
+    //RLC  rotate A left, bit 7 goes to carry and bit 0; 4c; os=1byte
+    0x07 => {
+      cpu_state.cc.cy = 0x80 == (cpu_state.a & 0x80);
+      cpu_state.a = cpu_state.a.rotate_left(1);
+    },
+
+    //NOP (undocumented) ;4c ;os=1byte
+    0x08 => {},
+
+    //DAD B  add register pair BC to HL  ; 10c, ox=1
+    0x09 => { let bc = bc(cpu_state); dad(cpu_state, bc); },
+
+    //LDAX B  load A indirect from the address in BC; 7c; os=1byte
+    0x0a => { cpu_state.a = cpu_state.memory.read(bc(cpu_state)); },
+
+    //DCX B ;5c; os=1byte decrement register BC
+    0x0b => { let res = bc(cpu_state).wrapping_sub(1); set_bc(cpu_state, res); },
+
+    //INR C ;5c; os=1byte increment register C
+    0x0c => { cpu_state.c = inr(cpu_state, cpu_state.c); },
+
+    //DCR C  decrement single u8 register C 5c; os=1
+    0x0d => { cpu_state.c = dcr(cpu_state, cpu_state.c); },
+
+    //MVI C, u8 move immediate to C; 7c; os=2byte
+    0x0e => { cpu_state.c = operation_arg1; cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RRC  rotate A right, bit 0 goes to carry and bit 7; 4c; os=1byte
+    0x0f => {
+      cpu_state.cc.cy = 0x01 == (cpu_state.a & 0x01);
+      cpu_state.a = cpu_state.a.rotate_right(1);
+    },
+
+    //NOP (undocumented) ;4c ;os=1byte
+    0x10 => {},
+
+    //LXI D, u16 ; 10c; os=3byte  load intermediate to combined register DE (just called D as 16 bit register)
+    0x11 => { cpu_state.e = operation_arg1; cpu_state.d = operation_arg2; cpu_state.pc = cpu_state.pc.wrapping_add(2); },
+
+    //STAX D  store A indirect to the address in DE; 7c; os=1byte
+    0x12 => { let offset = de(cpu_state); cpu_state.memory.write(offset, cpu_state.a); },
+
+    //INX D ;5c; os=1byte increment register DE
+    0x13 => { let res = de(cpu_state).wrapping_add(1); set_de(cpu_state, res); },
+
+    //INR D ;5c; os=1byte increment register D
+    0x14 => { cpu_state.d = inr(cpu_state, cpu_state.d); },
+
+    //DCR D ;5c; os=1byte decrement register D
+    0x15 => { cpu_state.d = dcr(cpu_state, cpu_state.d); },
+
+    //MVI D, u8 move immediate to D; 7c; os=2byte
+    0x16 => { cpu_state.d = operation_arg1; cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RAL  rotate A left through carry; 4c; os=1byte
+    0x17 => {
+      let carry = cpu_state.cc.cy as u8;
+      cpu_state.cc.cy = 0x80 == (cpu_state.a & 0x80);
+      cpu_state.a = cpu_state.a << 1 | carry;
+    },
+
+    //NOP (undocumented) ;4c ;os=1byte
+    0x18 => {},
+
+    //DAD D  add register pair DE to HL  ; 10c, ox=1
+    0x19 => { let de = de(cpu_state); dad(cpu_state, de); },
+
+    //LDAX D load ; 7c; os=1byte; load memory indirect from combinded register DE (just called D as 16 bit register) to A
+    0x1a => {
+      let memory_offset: u16 = ((cpu_state.d as u16) << 8) | (cpu_state.e as u16);
+      cpu_state.a = cpu_state.memory.read(memory_offset);
+    },
+
+    //DCX D ;5c; os=1byte decrement register DE
+    0x1b => { let res = de(cpu_state).wrapping_sub(1); set_de(cpu_state, res); },
+
+    //INR E ;5c; os=1byte increment register E
+    0x1c => { cpu_state.e = inr(cpu_state, cpu_state.e); },
+
+    //DCR E ;5c; os=1byte decrement register E
+    0x1d => { cpu_state.e = dcr(cpu_state, cpu_state.e); },
+
+    //MVI E, u8 move immediate to E; 7c; os=2byte
+    0x1e => { cpu_state.e = operation_arg1; cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RAR  rotate A right through carry; 4c; os=1byte
+    0x1f => {
+      let carry = cpu_state.cc.cy as u8;
+      cpu_state.cc.cy = 0x01 == (cpu_state.a & 0x01);
+      cpu_state.a = cpu_state.a >> 1 | carry << 7;
+    },
+
+    //NOP (undocumented) ;4c ;os=1byte
+    0x20 => {},
+
+    //LXI H, u16 ; 10c; os=3byte  load intermediate to combined register HL (just called H as 16 bit register)
+    0x21 => { cpu_state.l = operation_arg1; cpu_state.h = operation_arg2; cpu_state.pc = cpu_state.pc.wrapping_add(2); },
+
+    //SHLD u16  store L at the address and H at the address + 1; 16c; os=3byte
+    0x22 => {
+      cpu_state.memory.write(operation_address, cpu_state.l);
+      cpu_state.memory.write(operation_address.wrapping_add(1), cpu_state.h);
+      cpu_state.pc = cpu_state.pc.wrapping_add(2);
+    },
+
+    //INX H ;5c; os=1byte  increment register HL
+    0x23 => { let res = hl(cpu_state).wrapping_add(1); set_hl(cpu_state, res); },
+
+    //INR H ;5c; os=1byte increment register H
+    0x24 => { cpu_state.h = inr(cpu_state, cpu_state.h); },
+
+    //DCR H ;5c; os=1byte decrement register H
+    0x25 => { cpu_state.h = dcr(cpu_state, cpu_state.h); },
+
+    //MVI H, u8 move immediate to H; 7c; os=2byte
+    0x26 => { cpu_state.h = operation_arg1; cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //DAA  decimal adjust A after a BCD addition; 4c; os=1byte
+    0x27 => { daa(cpu_state); },
+
+    //NOP (undocumented) ;4c ;os=1byte
+    0x28 => {},
+
+    //DAD H  add register pair HL to HL (HLx2) ; 10c, ox=1
+    0x29 => { let hl = hl(cpu_state); dad(cpu_state, hl); },
+
+    //LHLD u16  load L from the address and H from the address + 1; 16c; os=3byte
+    0x2a => {
+      cpu_state.l = cpu_state.memory.read(operation_address);
+      cpu_state.h = cpu_state.memory.read(operation_address.wrapping_add(1));
+      cpu_state.pc = cpu_state.pc.wrapping_add(2);
+    },
+
+    //DCX H ;5c; os=1byte decrement register HL
+    0x2b => { let res = hl(cpu_state).wrapping_sub(1); set_hl(cpu_state, res); },
+
+    //INR L ;5c; os=1byte increment register L
+    0x2c => { cpu_state.l = inr(cpu_state, cpu_state.l); },
+
+    //DCR L ;5c; os=1byte decrement register L
+    0x2d => { cpu_state.l = dcr(cpu_state, cpu_state.l); },
+
+    //MVI L, u8 move immediate to L; 7c; os=2byte
+    0x2e => { cpu_state.l = operation_arg1; cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //CMA  complement A; 4c; os=1byte
+    0x2f => { cpu_state.a = !cpu_state.a; },
+
+    //NOP (undocumented) ;4c ;os=1byte
+    0x30 => {},
+
+    //LXI sp, u16   Load registerpair u16 immediate to stack pointer(which is u16) ;10c ;os=3byte
+    0x31 => { cpu_state.sp = (operation_arg2 as u16) << 8 | (operation_arg1 as u16); cpu_state.pc = cpu_state.pc.wrapping_add(2); },
+
+    //STA u16  store A direct; 13c; os=3byte
+    0x32 => { cpu_state.memory.write(operation_address, cpu_state.a); cpu_state.pc = cpu_state.pc.wrapping_add(2); },
+
+    //INX SP ;5c; os=1byte increment the stack pointer
+    0x33 => { cpu_state.sp = cpu_state.sp.wrapping_add(1); },
+
+    //INR M ;10c; os=1byte increment the memory location pointed to by HL
+    0x34 => {
+      let offset = hl(cpu_state);
+      let value = cpu_state.memory.read(offset);
+      let res = inr(cpu_state, value);
+      cpu_state.memory.write(offset, res);
+    },
+
+    //DCR M ;10c; os=1byte decrement the memory location pointed to by HL
+    0x35 => {
+      let offset = hl(cpu_state);
+      let value = cpu_state.memory.read(offset);
+      let res = dcr(cpu_state, value);
+      cpu_state.memory.write(offset, res);
+    },
+
+    //MVI M,byte move immediate memory; 10c; os=2byte
+    0x36 => {
+      let offset: u16 = ((cpu_state.h as u16) << 8) | cpu_state.l as u16;
+      cpu_state.memory.write(offset, operation_arg1);
+      cpu_state.pc = cpu_state.pc.wrapping_add(1);
+    },
+
+    //STC  set carry; 4c; os=1byte
+    0x37 => { cpu_state.cc.cy = true; },
+
+    //NOP (undocumented) ;4c ;os=1byte
+    0x38 => {},
+
+    //DAD SP  add the stack pointer to HL; 10c; os=1byte
+    0x39 => { let sp = cpu_state.sp; dad(cpu_state, sp); },
+
+    //LDA  load register A direct
+    0x3a => {
+      let offset: u16 = (operation_arg2 as u16) << 8 | operation_arg1 as u16;
+      cpu_state.a = cpu_state.memory.read(offset);
+      cpu_state.pc = cpu_state.pc.wrapping_add(2);
+    }
+
+    //DCX SP ;5c; os=1byte decrement the stack pointer
+    0x3b => { cpu_state.sp = cpu_state.sp.wrapping_sub(1); },
+
+    //INR A ;5c; os=1byte increment register A
+    0x3c => { cpu_state.a = inr(cpu_state, cpu_state.a); },
+
+    //DCR A ;5c; os=1byte decrement register A
+    0x3d => { cpu_state.a = dcr(cpu_state, cpu_state.a); },
+
+    //MVI A, u8 move immediate to A; 7c; os=2byte
+    0x3e => { cpu_state.a = operation_arg1; cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //CMC  complement carry; 4c; os=1byte
+    0x3f => { cpu_state.cc.cy = !cpu_state.cc.cy; },
+
+    //HLT  halt the processor until the next interrupt; 7c; os=1byte
+    0x76 => { cpu_state.halted = true; },
+
+    //MOV dst,src ; 0b01dddsss; 5c, 7c when one side is M; os=1byte
+    0x40..=0x7f => {
+      let src = operation_code & 0x07;
+      let dst = (operation_code >> 3) & 0x07;
+      let value = read_register(cpu_state, src);
+      write_register(cpu_state, dst, value);
+    },
+
+    //ADD ADC SUB SBB ANA XRA ORA CMP with register operand ; 0b10ooosss; 4c, 7c for M; os=1byte
+    0x80..=0xbf => {
+      let src = operation_code & 0x07;
+      let value = read_register(cpu_state, src);
+      alu(cpu_state, (operation_code >> 3) & 0x07, value);
+    },
+
+    //RNZ  return on not zero; 5c/11c; os=1byte
+    0xc0 => { if !cpu_state.cc.z { ret(cpu_state); taken = true; } },
+
+    //POP B  pop register pair BC 10c; os=1;
+    0xc1 => {
+      cpu_state.c = cpu_state.memory.read(cpu_state.sp);
+      cpu_state.b = cpu_state.memory.read(cpu_state.sp.wrapping_add(1));
+      cpu_state.sp = cpu_state.sp.wrapping_add(2);
+    },
+
+    //JNZ adress u16 Jump on none zero ; 10c; os=3byte
+    0xc2 => {
+      if !cpu_state.cc.z {
+        cpu_state.pc = (operation_arg2 as u16) << 8 | (operation_arg1 as u16);
+      } else {
+        cpu_state.pc = cpu_state.pc.wrapping_add(2);
+      }
+    },
+
+    //JMP u16  jump to u16 adress ;10c ; os=3byte
+    0xc3 => { cpu_state.pc = (operation_arg2 as u16) << 8 | (operation_arg1 as u16); },
+
+    //CNZ u16  call on not zero; 11c/17c; os=3byte
+    0xc4 => { let cond = !cpu_state.cc.z; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //PUSH B   push the register pair BC an the stack  11c; os=1
+    0xc5 => {
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(1), cpu_state.b);
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(2), cpu_state.c);
+      cpu_state.sp = cpu_state.sp.wrapping_sub(2);
+    },
+
+    //ADI u8  add immediate to A; 7c; os=2byte
+    0xc6 => { alu(cpu_state, 0, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 0  call 0x0000; 11c; os=1byte
+    0xc7 => { rst(cpu_state, 0); },
+
+    //RZ  return on zero; 5c/11c; os=1byte
+    0xc8 => { if cpu_state.cc.z { ret(cpu_state); taken = true; } },
+
+    //RET ;10c; os=1byte
+    0xc9 => {
+      //load return adress from stack in to program counter
+      cpu_state.pc = cpu_state.memory.read(cpu_state.sp) as u16 | ((cpu_state.memory.read(cpu_state.sp.wrapping_add(1)) as u16) << 8);
+      cpu_state.sp = cpu_state.sp.wrapping_add(2); // remove address from stack
+    },
+
+    //JZ u16  jump on zero; 10c; os=3byte
+    0xca => { let cond = cpu_state.cc.z; jump_if(cpu_state, cond, operation_address); },
+
+    //JMP u16 (undocumented) ;10c ; os=3byte
+    0xcb => { cpu_state.pc = operation_address; },
+
+    //CZ u16  call on zero; 11c/17c; os=3byte
+    0xcc => { let cond = cpu_state.cc.z; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //CALL adr u16 ;17; os=3byte
+    0xcd => {
+      let ret: u16 = cpu_state.pc.wrapping_add(2); // save return adress (3 byte after this 3 byte instr.) on the stack
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(1), (ret >> 8) as u8); // -- as u8 == & 0xff -- bitmask lower 8 bits of return addr. to write in higher stack bits
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(2), ret as u8); // -- as u8 == & -- 0xff bitmask higher 8 bits to write to lower stack bits
+
+      cpu_state.sp = cpu_state.sp.wrapping_sub(2);  // stack grows down
+      cpu_state.pc = (operation_arg2 as u16) << 8 | (operation_arg1 as u16); // jump to destination
+    },
+
+    //ACI u8  add immediate with carry to A; 7c; os=2byte
+    0xce => { alu(cpu_state, 1, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 1  call 0x0008; 11c; os=1byte
+    0xcf => { rst(cpu_state, 1); },
+
+    //RNC  return on no carry; 5c/11c; os=1byte
+    0xd0 => { if !cpu_state.cc.cy { ret(cpu_state); taken = true; } },
+
+    //POP D  pop register pair DE 10c; os=1;
+    0xd1 => {
+      cpu_state.e = cpu_state.memory.read(cpu_state.sp);
+      cpu_state.d = cpu_state.memory.read(cpu_state.sp.wrapping_add(1));
+      cpu_state.sp = cpu_state.sp.wrapping_add(2);
+    },
+
+    //JNC u16  jump on no carry; 10c; os=3byte
+    0xd2 => { let cond = !cpu_state.cc.cy; jump_if(cpu_state, cond, operation_address); },
+
+    //OUT u8  .. outputs the contend of register A to specified data port; 10c; os=2byte
+    0xd3 => { cpu_state.io.output(operation_arg1, cpu_state.a); cpu_state.pc = cpu_state.pc.wrapping_add(1); }
+
+    //CNC u16  call on no carry; 11c/17c; os=3byte
+    0xd4 => { let cond = !cpu_state.cc.cy; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //PUSH D push register pair DE to stack; 11c; os=1byte
+    0xd5 => {
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(1), cpu_state.d);
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(2), cpu_state.e);
+      cpu_state.sp = cpu_state.sp.wrapping_sub(2);
+    },
+
+    //SUI u8  subtract immediate from A; 7c; os=2byte
+    0xd6 => { alu(cpu_state, 2, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 2  call 0x0010; 11c; os=1byte
+    0xd7 => { rst(cpu_state, 2); },
+
+    //RC  return on carry; 5c/11c; os=1byte
+    0xd8 => { if cpu_state.cc.cy { ret(cpu_state); taken = true; } },
+
+    //RET (undocumented) ;10c; os=1byte
+    0xd9 => { ret(cpu_state); },
+
+    //JC u16  jump on carry; 10c; os=3byte
+    0xda => { let cond = cpu_state.cc.cy; jump_if(cpu_state, cond, operation_address); },
+
+    //IN u8  read the specified data port into A; 10c; os=2byte
+    0xdb => { cpu_state.a = cpu_state.io.input(operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //CC u16  call on carry; 11c/17c; os=3byte
+    0xdc => { let cond = cpu_state.cc.cy; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //CALL u16 (undocumented) ;17c; os=3byte
+    0xdd => { call_if(cpu_state, true, operation_address); },
+
+    //SBI u8  subtract immediate with borrow from A; 7c; os=2byte
+    0xde => { alu(cpu_state, 3, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 3  call 0x0018; 11c; os=1byte
+    0xdf => { rst(cpu_state, 3); },
+
+    //RPO  return on parity odd; 5c/11c; os=1byte
+    0xe0 => { if !cpu_state.cc.p { ret(cpu_state); taken = true; } },
+
+    //POP H  pop register pair HL 10c; os=1;
+    0xe1 => {
+      cpu_state.l = cpu_state.memory.read(cpu_state.sp);
+      cpu_state.h = cpu_state.memory.read(cpu_state.sp.wrapping_add(1));
+      cpu_state.sp = cpu_state.sp.wrapping_add(2);
+    },
+
+    //JPO u16  jump on parity odd; 10c; os=3byte
+    0xe2 => { let cond = !cpu_state.cc.p; jump_if(cpu_state, cond, operation_address); },
+
+    //XTHL  exchange HL with the top of the stack; 18c; os=1byte
+    0xe3 => {
+      let sp = cpu_state.sp;
+      let sp_high = cpu_state.sp.wrapping_add(1);
+      let l = cpu_state.memory.read(sp);
+      let h = cpu_state.memory.read(sp_high);
+      cpu_state.memory.write(sp, cpu_state.l);
+      cpu_state.memory.write(sp_high, cpu_state.h);
+      cpu_state.l = l;
+      cpu_state.h = h;
+    },
+
+    //CPO u16  call on parity odd; 11c/17c; os=3byte
+    0xe4 => { let cond = !cpu_state.cc.p; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //PUSH H push register pair HL to stack; 11c; os=1byte
+    0xe5 => {
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(1), cpu_state.h);
+      cpu_state.memory.write(cpu_state.sp.wrapping_sub(2), cpu_state.l);
+      cpu_state.sp = cpu_state.sp.wrapping_sub(2);
+    },
+
+    //ANI u8  and immediate with A; 7c; os=2byte
+    0xe6 => { alu(cpu_state, 4, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 4  call 0x0020; 11c; os=1byte
+    0xe7 => { rst(cpu_state, 4); },
+
+    //RPE  return on parity even; 5c/11c; os=1byte
+    0xe8 => { if cpu_state.cc.p { ret(cpu_state); taken = true; } },
+
+    //PCHL  jump to the address in HL; 5c; os=1byte
+    0xe9 => { cpu_state.pc = hl(cpu_state); },
+
+    //JPE u16  jump on parity even; 10c; os=3byte
+    0xea => { let cond = cpu_state.cc.p; jump_if(cpu_state, cond, operation_address); },
+
+    //XCHG   exchange register pairs DE <-> HL 4c; os=1
+    0xeb => {
+      let d: u8 = cpu_state.d;
+      let e: u8 = cpu_state.e;
+      cpu_state.d = cpu_state.h;
+      cpu_state.e = cpu_state.l;
+      cpu_state.h = d;
+      cpu_state.l = e;
+    },
+
+    //CPE u16  call on parity even; 11c/17c; os=3byte
+    0xec => { let cond = cpu_state.cc.p; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //CALL u16 (undocumented) ;17c; os=3byte
+    0xed => { call_if(cpu_state, true, operation_address); },
+
+    //XRI u8  exclusive or immediate with A; 7c; os=2byte
+    0xee => { alu(cpu_state, 5, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 5  call 0x0028; 11c; os=1byte
+    0xef => { rst(cpu_state, 5); },
+
+    //RP  return on plus (sign not set); 5c/11c; os=1byte
+    0xf0 => { if !cpu_state.cc.s { ret(cpu_state); taken = true; } },
+
+    //POP PSW  pop A and the flags; 10c; os=1byte
+    0xf1 => {
+      let psw = pop(cpu_state);
+      cpu_state.a = (psw >> 8) as u8;
+      set_flags(cpu_state, psw as u8);
+    },
+
+    //JP u16  jump on plus (sign not set); 10c; os=3byte
+    0xf2 => { let cond = !cpu_state.cc.s; jump_if(cpu_state, cond, operation_address); },
+
+    //DI  disable interrupts; 4c; os=1byte
+    0xf3 => { cpu_state.int_enable = 0; },
+
+    //CP u16  call on plus (sign not set); 11c/17c; os=3byte
+    0xf4 => { let cond = !cpu_state.cc.s; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //PUSH PSW  push A and the flags; 11c; os=1byte
+    0xf5 => {
+      let psw = (cpu_state.a as u16) << 8 | flags(cpu_state) as u16;
+      push(cpu_state, psw);
+    },
+
+    //ORI u8  or immediate with A; 7c; os=2byte
+    0xf6 => { alu(cpu_state, 6, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 6  call 0x0030; 11c; os=1byte
+    0xf7 => { rst(cpu_state, 6); },
+
+    //RM  return on minus (sign set); 5c/11c; os=1byte
+    0xf8 => { if cpu_state.cc.s { ret(cpu_state); taken = true; } },
+
+    //SPHL  load the stack pointer from HL; 5c; os=1byte
+    0xf9 => { cpu_state.sp = hl(cpu_state); },
+
+    //JM u16  jump on minus (sign set); 10c; os=3byte
+    0xfa => { let cond = cpu_state.cc.s; jump_if(cpu_state, cond, operation_address); },
+
+    //EI  enable interrupts after the next instruction; 4c; os=1byte
+    0xfb => { cpu_state.int_enable = 1; cpu_state.int_delay = true; },
+
+    //CM u16  call on minus (sign set); 11c/17c; os=3byte
+    0xfc => { let cond = cpu_state.cc.s; call_if(cpu_state, cond, operation_address); taken = cond; },
+
+    //CALL u16 (undocumented) ;17c; os=3byte
+    0xfd => { call_if(cpu_state, true, operation_address); },
+
+    //CPI byte compare immediate with A ;7c ; os=2byte
+    0xfe => { alu(cpu_state, 7, operation_arg1); cpu_state.pc = cpu_state.pc.wrapping_add(1); },
+
+    //RST 7  call 0x0038; 11c; os=1byte
+    0xff => { rst(cpu_state, 7); },
+
+  }
+  let info = &OPCODES[operation_code as usize];
+  let operation_cycles = if taken { info.cycles_taken } else { info.cycles } as u32;
+  cpu_state.cycles += operation_cycles as u64;
+  operation_cycles
+}
+
+/// register pair BC as one 16 bit value
+fn bc<M: Bus, P: Io>(cpu_state: &CpuState<M, P>) -> u16 { (cpu_state.b as u16) << 8 | cpu_state.c as u16 }
+
+/// register pair DE as one 16 bit value
+fn de<M: Bus, P: Io>(cpu_state: &CpuState<M, P>) -> u16 { (cpu_state.d as u16) << 8 | cpu_state.e as u16 }
+
+/// register pair HL as one 16 bit value, also the address of the M operand
+fn hl<M: Bus, P: Io>(cpu_state: &CpuState<M, P>) -> u16 { (cpu_state.h as u16) << 8 | cpu_state.l as u16 }
+
+fn set_bc<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, value: u16) { cpu_state.b = (value >> 8) as u8; cpu_state.c = value as u8; }
+
+fn set_de<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, value: u16) { cpu_state.d = (value >> 8) as u8; cpu_state.e = value as u8; }
+
+fn set_hl<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, value: u16) { cpu_state.h = (value >> 8) as u8; cpu_state.l = value as u8; }
+
+/// reads one of the 8 bit operands by its 3 bit code: B C D E H L M A
+fn read_register<M: Bus, P: Io>(cpu_state: &CpuState<M, P>, code: u8) -> u8 {
+  match code & 0x07 {
+    0 => cpu_state.b,
+    1 => cpu_state.c,
+    2 => cpu_state.d,
+    3 => cpu_state.e,
+    4 => cpu_state.h,
+    5 => cpu_state.l,
+    6 => cpu_state.memory.read(hl(cpu_state)),
+    _ => cpu_state.a,
+  }
+}
+
+/// writes one of the 8 bit operands by its 3 bit code: B C D E H L M A
+fn write_register<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, code: u8, value: u8) {
+  match code & 0x07 {
+    0 => cpu_state.b = value,
+    1 => cpu_state.c = value,
+    2 => cpu_state.d = value,
+    3 => cpu_state.e = value,
+    4 => cpu_state.h = value,
+    5 => cpu_state.l = value,
+    6 => { let offset = hl(cpu_state); cpu_state.memory.write(offset, value); },
+    _ => cpu_state.a = value,
+  }
+}
+
+/// sets zero, sign and parity from a 8 bit result
+fn set_zsp<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, res: u8) {
+  cpu_state.cc.z = res == 0;
+  cpu_state.cc.s = 0x80 == (res & 0x80);
+  cpu_state.cc.p = parity(res, 8);
+}
+
+/// flags packed in the PSW layout: S Z 0 AC 0 P 1 CY
+fn flags<M: Bus, P: Io>(cpu_state: &CpuState<M, P>) -> u8 {
+  (cpu_state.cc.s as u8) << 7 |
+  (cpu_state.cc.z as u8) << 6 |
+  (cpu_state.cc.ac as u8) << 4 |
+  (cpu_state.cc.p as u8) << 2 |
+  0x02 |
+  (cpu_state.cc.cy as u8)
+}
+
+/// unpacks the flags from the PSW layout, see `flags`
+fn set_flags<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, psw: u8) {
+  cpu_state.cc.s = 0x80 == (psw & 0x80);
+  cpu_state.cc.z = 0x40 == (psw & 0x40);
+  cpu_state.cc.ac = 0x10 == (psw & 0x10);
+  cpu_state.cc.p = 0x04 == (psw & 0x04);
+  cpu_state.cc.cy = 0x01 == (psw & 0x01);
+}
+
+/// 8 bit addition, sets all flags and returns the result
+/// the auxiliary carry is the carry out of bit 3
+fn add<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, lhs: u8, rhs: u8, carry: bool) -> u8 {
+  let res: u16 = lhs as u16 + rhs as u16 + carry as u16;
+  set_zsp(cpu_state, res as u8);
+  cpu_state.cc.cy = res > 0xff;
+  cpu_state.cc.ac = (lhs & 0x0f) + (rhs & 0x0f) + carry as u8 > 0x0f;
+  res as u8
+}
+
+/// 8 bit subtraction, sets all flags and returns the result, carry is set on borrow
+/// the 8080 subtracts by adding the two's complement, so the auxiliary carry
+/// is the carry out of bit 3 of that addition and not a borrow
+fn sub<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, lhs: u8, rhs: u8, borrow: bool) -> u8 {
+  let res: u16 = (lhs as u16).wrapping_sub(rhs as u16).wrapping_sub(borrow as u16);
+  set_zsp(cpu_state, res as u8);
+  cpu_state.cc.cy = res > 0xff;
+  cpu_state.cc.ac = (lhs & 0x0f) + (!rhs & 0x0f) + !borrow as u8 > 0x0f;
+  res as u8
+}
+
+/// the arithmetic and logic group by its 3 bit code: ADD ADC SUB SBB ANA XRA ORA CMP
+fn alu<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, code: u8, value: u8) {
+  let a = cpu_state.a;
+  let carry = cpu_state.cc.cy;
+  match code & 0x07 {
+    0 => { cpu_state.a = add(cpu_state, a, value, false); },
+    1 => { cpu_state.a = add(cpu_state, a, value, carry); },
+    2 => { cpu_state.a = sub(cpu_state, a, value, false); },
+    3 => { cpu_state.a = sub(cpu_state, a, value, carry); },
+    4 => { cpu_state.a = a & value; logic_flags(cpu_state); cpu_state.cc.ac = 0x08 == ((a | value) & 0x08); },
+    5 => { cpu_state.a = a ^ value; logic_flags(cpu_state); },
+    6 => { cpu_state.a = a | value; logic_flags(cpu_state); },
+    _ => { sub(cpu_state, a, value, false); },
+  }
+}
+
+/// flags after ANA, XRA and ORA: zero, sign and parity from A, carry and auxiliary carry cleared
+/// ANA sets the auxiliary carry afterwards from bit 3 of its operands, like the 8080 does
+fn logic_flags<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>) {
+  let a = cpu_state.a;
+  set_zsp(cpu_state, a);
+  cpu_state.cc.cy = false;
+  cpu_state.cc.ac = false;
+}
+
+/// increment for INR, carry is not affected
+fn inr<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, value: u8) -> u8 {
+  let res = value.wrapping_add(1);
+  set_zsp(cpu_state, res);
+  cpu_state.cc.ac = (res & 0x0f) == 0x00;
+  res
+}
+
+/// decrement for DCR, carry is not affected
+/// like `sub` the auxiliary carry is the carry out of bit 3 of the complement addition
+fn dcr<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, value: u8) -> u8 {
+  let res = value.wrapping_sub(1);
+  set_zsp(cpu_state, res);
+  cpu_state.cc.ac = (res & 0x0f) != 0x0f;
+  res
+}
+
+/// decimal adjust A, turns the binary sum of two BCD numbers into the BCD sum
+/// adds 0x06 if the low nibble is above 9 or AC is set, then 0x60 if the high
+/// nibble (after the first step) is above 9 or CY is set; CY is never cleared
+fn daa<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>) {
+  let a = cpu_state.a;
+  let mut correction: u8 = 0;
+  let mut carry = cpu_state.cc.cy;
+
+  if cpu_state.cc.ac || (a & 0x0f) > 9 {
+    correction |= 0x06;
+  }
+  if carry || a > 0x99 {
+    correction |= 0x60;
+    carry = true;
+  }
+
+  cpu_state.a = add(cpu_state, a, correction, false);
+  cpu_state.cc.cy = carry;
+}
+
+/// adds a register pair to HL, only the carry flag is affected
+fn dad<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, value: u16) {
+  let res: u32 = hl(cpu_state) as u32 + value as u32;
+  set_hl(cpu_state, res as u16);
+  cpu_state.cc.cy = (res & 0xffff0000) != 0;
+}
+
+/// pushes a 16 bit value, high byte first; the stack grows down
+fn push<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, value: u16) {
+  cpu_state.memory.write(cpu_state.sp.wrapping_sub(1), (value >> 8) as u8);
+  cpu_state.memory.write(cpu_state.sp.wrapping_sub(2), value as u8);
+  cpu_state.sp = cpu_state.sp.wrapping_sub(2);
+}
+
+/// pops a 16 bit value from the stack
+fn pop<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>) -> u16 {
+  let low = cpu_state.memory.read(cpu_state.sp) as u16;
+  let high = cpu_state.memory.read(cpu_state.sp.wrapping_add(1)) as u16;
+  cpu_state.sp = cpu_state.sp.wrapping_add(2);
+  high << 8 | low
+}
+
+/// returns to the address on top of the stack
+fn ret<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>) {
+  cpu_state.pc = pop(cpu_state);
+}
+
+/// jumps to `address` if `condition` holds, otherwise skips the address operand
+fn jump_if<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, condition: bool, address: u16) {
+  if condition {
+    cpu_state.pc = address;
+  } else {
+    cpu_state.pc = cpu_state.pc.wrapping_add(2);
+  }
+}
+
+/// calls `address` if `condition` holds, otherwise skips the address operand
+fn call_if<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, condition: bool, address: u16) {
+  cpu_state.pc = cpu_state.pc.wrapping_add(2);
+  if condition {
+    let ret = cpu_state.pc;
+    push(cpu_state, ret);
+    cpu_state.pc = address;
+  }
+}
+
+/// restart: calls the 8 byte vector `n` * 8
+fn rst<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, n: u8) {
+  let ret = cpu_state.pc;
+  push(cpu_state, ret);
+  cpu_state.pc = (n as u16) << 3;
+}
+
+#[test]
+fn bus_test() {
+  // 1 KiB of ROM at 0x0000 mirrored over the lower half, RAM in the upper half
+  struct Rom { rom: Vec<u8>, ram: Vec<u8> }
+  impl Bus for Rom {
+    fn read(&self, address: u16) -> u8 {
+      if address < 0x8000 { self.rom[address as usize & 0x03ff] } else { self.ram[address as usize - 0x8000] }
+    }
+    fn write(&mut self, address: u16, value: u8) {
+      if address >= 0x8000 { self.ram[address as usize - 0x8000] = value; }
+    }
+  }
+
+  let mut rom = vec![0; 0x0400];
+  let program = [
+    0x21, 0x00, 0x00,  // LXI H, $0000
+    0x34,              // INR M, ignored by the ROM
+    0x3e, 0x2a,        // MVI A, #$2a
+    0x32, 0x00, 0x90,  // STA $9000
+    0xc3, 0x00, 0x04,  // JMP $0400, the mirror of $0000
+  ];
+  rom[..program.len()].copy_from_slice(&program);
+  let mut cpu_state = init_cpu_with(Rom { rom, ram: vec![0; 0x8000] }, NullIo);
+
+  for _ in 0..6 {
+    emulate(&mut cpu_state).unwrap();  // the last one is LXI H from the mirror
+  }
+  assert_eq!(cpu_state.memory.read(0x0000), 0x21);
+  assert_eq!(cpu_state.memory.read(0x9000), 0x2a);
+  assert_eq!(cpu_state.pc, 0x0403);
+}
+
+#[test]
+fn io_test() {
+  // a latch on port 7 that reads back the last value written, inverted
+  struct Latch { value: u8 }
+  impl Io for Latch {
+    fn input(&mut self, port: u8) -> u8 {
+      if port == 7 { !self.value } else { 0xff }
+    }
+    fn output(&mut self, port: u8, value: u8) {
+      if port == 7 { self.value = value; }
+    }
+  }
+
+  let mut cpu_state = init_cpu_with(FlatMemory::new(), Latch { value: 0 });
+  let program = [
+    0x3e, 0x0f,  // MVI A, #$0f
+    0xd3, 0x07,  // OUT 7
+    0xdb, 0x07,  // IN 7
+    0x47,        // MOV B, A
+    0xdb, 0x01,  // IN 1
+  ];
+  cpu_state.memory[..program.len()].copy_from_slice(&program);
+
+  for _ in 0..5 {
+    emulate(&mut cpu_state).unwrap();
+  }
+  assert_eq!(cpu_state.io.value, 0x0f);
+  assert_eq!(cpu_state.b, 0xf0);
+  assert_eq!(cpu_state.a, 0xff);
+}
+
+#[test]
+fn error_test() {
+  use bus::BusFault;
+
+  // RAM below 0x8000, nothing mapped above
+  struct HalfMemory { ram: Vec<u8>, fault: Option<BusFault> }
+  impl Bus for HalfMemory {
+    fn read(&self, address: u16) -> u8 {
+      if address < 0x8000 { self.ram[address as usize] } else { 0xff }
+    }
+    fn write(&mut self, address: u16, value: u8) {
+      if address < 0x8000 { self.ram[address as usize] = value; } else { self.fault = self.fault.or(Some(BusFault::UnmappedWrite(address))); }
+    }
+    fn take_fault(&mut self) -> Option<BusFault> {
+      self.fault.take()
+    }
+  }
+
+  let mut cpu_state = init_cpu_with(HalfMemory { ram: vec![0; 0x8000], fault: None }, NullIo);
+  let program = [
+    0xc5,              // PUSH B with SP 0, wraps to the unmapped top of memory
+    0x31, 0x00, 0x01,  // LXI SP, $0100
+    0xc5,              // PUSH B
+    0x08,              // NOP (undocumented)
+    0x76,              // HLT
+  ];
+  cpu_state.memory.ram[..program.len()].copy_from_slice(&program);
+
+  let error = emulate(&mut cpu_state).unwrap_err();
+  assert_eq!(error.kind, ErrorKind::BusFault { pc: 0x0000, fault: BusFault::UnmappedWrite(0xffff) });
+  assert_eq!(error.registers.sp, 0xfffe);
+  assert_eq!(error.registers.pc, 0x0001);
+
+  emulate(&mut cpu_state).unwrap();
+  emulate(&mut cpu_state).unwrap();
+
+  cpu_state.strict = true;
+  let error = emulate(&mut cpu_state).unwrap_err();
+  assert_eq!(error.kind, ErrorKind::IllegalOpcode(0x08));
+  assert_eq!(error.registers.pc, 0x0005);
+  cpu_state.strict = false;
+
+  emulate(&mut cpu_state).unwrap();
+  assert_eq!(emulate(&mut cpu_state).unwrap(), 7);
+  assert_eq!(emulate(&mut cpu_state).unwrap_err().kind, ErrorKind::Halted);
+}
+
+/// Counts the number of 1 in binary format, true if the count is even
+pub fn parity(_x: u8, size: usize) -> bool {
+  let mut p = 0;      //number of ones
+  let mut x = _x;
+
+  for _ in 0..size {  // count every diget if its a one
+    if 1 == (x & 0x1) { p += 1; }
+    x >>= 1;
+  }
+
+  0 == (p & 0x1)      // true if the count is even
+}
+
+#[test]
+#[allow(clippy::bool_assert_comparison)]
+fn parity_test() {
+
+  assert_eq!(parity(0u8, 8), true); // zero is even .. ?
+  assert_eq!(parity(1u8, 8), false);
+  assert_eq!(parity(2u8, 8), false);
+  assert_eq!(parity(3u8, 8), true);
+  assert_eq!(parity(4u8, 8), false);
+  assert_eq!(parity(5u8, 8), true);
+  assert_eq!(parity(6u8, 8), true);
+  assert_eq!(parity(7u8, 8), false);
+  assert_eq!(parity(8u8, 8), false);
+  assert_eq!(parity(9u8, 8), true);
+
+}
+
+#[test]
+fn daa_bcd_addition_test() {
+  // every sum of two BCD bytes with and without carry in, ADC followed by DAA
+  let to_bcd = |x: u32| (((x / 10) % 10) << 4 | (x % 10)) as u8;
+  let mut cpu_state = init_cpu();
+
+  for lhs in 0..100u32 {
+    for rhs in 0..100u32 {
+      for carry in 0..2u32 {
+        cpu_state.a = to_bcd(lhs);
+        cpu_state.cc.cy = carry == 1;
+        alu(&mut cpu_state, 1, to_bcd(rhs));
+        daa(&mut cpu_state);
+
+        let sum = lhs + rhs + carry;
+        assert_eq!(cpu_state.a, to_bcd(sum % 100), "{} + {} + {}", lhs, rhs, carry);
+        assert_eq!(cpu_state.cc.cy, sum >= 100, "{} + {} + {}", lhs, rhs, carry);
+        assert_eq!(cpu_state.cc.z, sum % 100 == 0, "{} + {} + {}", lhs, rhs, carry);
+      }
+    }
+  }
+}
+
+#[test]
+fn daa_table_test() {
+  // reference DAA worked out nibble by nibble: 6 is added to the low nibble
+  // when it is above 9 or AC is set, 6 to the high nibble when CY is set or
+  // the byte is above 0x99; CY then tells whether the high nibble was
+  // corrected and AC whether the low correction carried out of bit 3
+  let reference = |a: u8, cy: bool, ac: bool| {
+    let (high, low) = (a >> 4, a & 0x0f);
+    let low_correction = if ac || low > 9 { 0x06 } else { 0x00 };
+    let high_correction = cy || high > 9 || (high == 9 && low > 9);
+    let res = a.wrapping_add(low_correction).wrapping_add(if high_correction { 0x60 } else { 0x00 });
+    (res, high_correction, low + low_correction > 0x0f)
+  };
+
+  // (A, CY, AC) before DAA -> (A, CY, AC) after DAA on an 8080, including non BCD inputs
+  let table: [(u8, bool, bool, u8, bool, bool); 12] = [
+    (0x00, false, false, 0x00, false, false),
+    (0x00, false, true,  0x06, false, false),
+    (0x00, true,  false, 0x60, true,  false),
+    (0x00, true,  true,  0x66, true,  false),
+    (0x09, false, false, 0x09, false, false),
+    (0x0a, false, false, 0x10, false, true),
+    (0x0f, false, false, 0x15, false, true),
+    (0x99, false, false, 0x99, false, false),
+    (0x9a, false, false, 0x00, true,  true),
+    (0xa0, false, false, 0x00, true,  false),
+    (0xff, false, false, 0x65, true,  true),
+    (0x3c, true,  false, 0xa2, true,  true),
+  ];
+  for &(a, cy, ac, res, res_cy, res_ac) in table.iter() {
+    assert_eq!(reference(a, cy, ac), (res, res_cy, res_ac), "reference DAA of {:02x}", a);
+  }
+
+  let mut cpu_state = init_cpu();
+  for a in 0..=0xffu8 {
+    for &(cy, ac) in [(false, false), (false, true), (true, false), (true, true)].iter() {
+      cpu_state.a = a;
+      cpu_state.cc.cy = cy;
+      cpu_state.cc.ac = ac;
+      daa(&mut cpu_state);
+
+      assert_eq!((cpu_state.a, cpu_state.cc.cy, cpu_state.cc.ac), reference(a, cy, ac), "DAA of {:02x}, cy {}, ac {}", a, cy, ac);
+    }
+  }
+}
+
+#[test]
+fn auxiliary_carry_test() {
+  let mut cpu_state = init_cpu();
+
+  cpu_state.a = 0x0f;
+  alu(&mut cpu_state, 0, 0x01);  // ADD: carry out of bit 3
+  assert!(cpu_state.cc.ac);
+  alu(&mut cpu_state, 0, 0x01);  // 0x10 + 0x01
+  assert!(!cpu_state.cc.ac);
+
+  cpu_state.a = 0x10;
+  alu(&mut cpu_state, 2, 0x01);  // SUB: borrow from bit 4 clears AC
+  assert!(!cpu_state.cc.ac);
+  cpu_state.a = 0x11;
+  alu(&mut cpu_state, 7, 0x01);  // CMP without borrow sets AC
+  assert!(cpu_state.cc.ac);
+
+  cpu_state.a = 0x08;
+  alu(&mut cpu_state, 4, 0x00);  // ANA: bit 3 of either operand
+  assert!(cpu_state.cc.ac);
+  alu(&mut cpu_state, 6, 0x08);  // ORA clears AC
+  assert!(!cpu_state.cc.ac);
+
+  assert_eq!(inr(&mut cpu_state, 0x0f), 0x10);
+  assert!(cpu_state.cc.ac);
+  assert_eq!(dcr(&mut cpu_state, 0x10), 0x0f);
+  assert!(!cpu_state.cc.ac);
+  assert_eq!(dcr(&mut cpu_state, 0x11), 0x10);
+  assert!(cpu_state.cc.ac);
+}
+
+#[test]
+fn cycles_test() {
+  let mut cpu_state = init_cpu();
+  let program = [
+    0x31, 0x00, 0x01,  // LXI SP, $0100   10
+    0xaf,              // XRA A           4
+    0xc4, 0x00, 0x00,  // CNZ $0000       11, not taken
+    0xcc, 0x0c, 0x00,  // CZ $000c        17, taken
+    0x76,              // HLT             7
+    0x00,
+    0xc0,              // RNZ             5, not taken
+    0xc8,              // RZ              11, taken
+  ];
+  for (idx, byte) in program.iter().enumerate() {
+    cpu_state.memory[idx] = *byte;
+  }
+
+  let expected: [u32; 7] = [10, 4, 11, 17, 5, 11, 7];
+  for cycles in expected.iter() {
+    assert_eq!(emulate(&mut cpu_state).unwrap(), *cycles);
+  }
+  assert!(cpu_state.halted);
+  assert_eq!(cpu_state.cycles, 65);
+}
+
+#[test]
+fn interrupt_test() {
+  let mut cpu_state = init_cpu();
+  let program = [
+    0x31, 0x00, 0x01,  // LXI SP, $0100
+    0xfb,              // EI
+    0x00,              // NOP
+    0x76,              // HLT
+    0x00,              // NOP
+  ];
+  for (idx, byte) in program.iter().enumerate() {
+    cpu_state.memory[idx] = *byte;
+  }
+  cpu_state.memory[0x0010] = 0xfb;  // RST 2 handler: EI
+  cpu_state.memory[0x0011] = 0xc9;  //                RET
+
+  interrupt_rst(&mut cpu_state, 2);
+  emulate(&mut cpu_state).unwrap();  // LXI, interrupts still disabled
+  emulate(&mut cpu_state).unwrap();  // EI
+  emulate(&mut cpu_state).unwrap();  // NOP, the instruction after EI is not interrupted
+  assert_eq!(cpu_state.pc, 0x0005);
+
+  assert_eq!(emulate(&mut cpu_state).unwrap(), 11);  // RST 2
+  assert_eq!(cpu_state.pc, 0x0010);
+  assert_eq!(cpu_state.int_enable, 0);
+  assert_eq!(cpu_state.memory[0x00fe], 0x05);  // return address
+  emulate(&mut cpu_state).unwrap();  // EI
+  emulate(&mut cpu_state).unwrap();  // RET
+  emulate(&mut cpu_state).unwrap();  // HLT
+  assert!(cpu_state.halted);
+  assert_eq!(emulate(&mut cpu_state).unwrap(), 4);  // idle
+  assert_eq!(cpu_state.pc, 0x0006);
+
+  // a CALL on the data bus returns to the halted program
+  interrupt(&mut cpu_state, [0xcd, 0x10, 0x00]);
+  assert_eq!(emulate(&mut cpu_state).unwrap(), 17);
+  assert!(!cpu_state.halted);
+  assert_eq!(cpu_state.pc, 0x0010);
+  emulate(&mut cpu_state).unwrap();  // EI
+  emulate(&mut cpu_state).unwrap();  // RET
+  assert_eq!(cpu_state.pc, 0x0006);
+  assert_eq!(cpu_state.sp, 0x0100);
+}
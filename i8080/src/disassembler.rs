@@ -0,0 +1,421 @@
+//! Turns 8080 machine code back into assembly text, or into typed `Instruction`s
+
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+use bus::Bus;
+use opcodes::OPCODES;
+
+/// 8 bit register operand, in the order of the 3 bit register code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register { B, C, D, E, H, L, M, A }
+
+/// 16 bit register pair operand, named by its high register
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegisterPair { B, D, H, SP, PSW }
+
+/// Condition of the conditional jumps, calls and returns, in the order of the 3 bit condition code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+  /// not zero
+  NZ,
+  /// zero
+  Z,
+  /// no carry
+  NC,
+  /// carry
+  C,
+  /// parity odd
+  PO,
+  /// parity even
+  PE,
+  /// plus
+  P,
+  /// minus
+  M,
+}
+
+/// One decoded 8080 instruction with its operands. The undocumented aliases
+/// decode to the instruction they alias.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+  Nop,
+  Lxi(RegisterPair, u16),
+  Stax(RegisterPair),
+  Inx(RegisterPair),
+  Inr(Register),
+  Dcr(Register),
+  Mvi(Register, u8),
+  Rlc,
+  Dad(RegisterPair),
+  Ldax(RegisterPair),
+  Dcx(RegisterPair),
+  Rrc,
+  Ral,
+  Rar,
+  Shld(u16),
+  Daa,
+  Lhld(u16),
+  Cma,
+  Sta(u16),
+  Stc,
+  Lda(u16),
+  Cmc,
+  /// destination, source
+  Mov(Register, Register),
+  Hlt,
+  Add(Register),
+  Adc(Register),
+  Sub(Register),
+  Sbb(Register),
+  Ana(Register),
+  Xra(Register),
+  Ora(Register),
+  Cmp(Register),
+  Rcc(Condition),
+  Pop(RegisterPair),
+  Jcc(Condition, u16),
+  Jmp(u16),
+  Ccc(Condition, u16),
+  Push(RegisterPair),
+  Adi(u8),
+  Rst(u8),
+  Ret,
+  Call(u16),
+  Aci(u8),
+  Out(u8),
+  Sui(u8),
+  In(u8),
+  Sbi(u8),
+  Xthl,
+  Pchl,
+  Xchg,
+  Di,
+  Ani(u8),
+  Xri(u8),
+  Sphl,
+  Ei,
+  Ori(u8),
+  Cpi(u8),
+}
+
+const REGISTERS: [Register; 8] = [Register::B, Register::C, Register::D, Register::E, Register::H, Register::L, Register::M, Register::A];
+const CONDITIONS: [Condition; 8] = [Condition::NZ, Condition::Z, Condition::NC, Condition::C, Condition::PO, Condition::PE, Condition::P, Condition::M];
+
+/// Decodes the instruction at `address`. Operand bytes past the end of the buffer read as 0.
+pub fn decode(instruction_buffer: &[u8], address: u16) -> Instruction {
+  use self::Instruction::*;
+
+  let byte_at = |offset: u16| instruction_buffer.get(address as usize + offset as usize).cloned().unwrap_or(0);
+  let operation_code = byte_at(0);
+  let byte = byte_at(1);
+  let word = (byte_at(2) as u16) << 8 | byte as u16;
+
+  // the fields most opcodes are built from: bits 5-3 and bits 2-0
+  let destination = REGISTERS[(operation_code >> 3 & 0x07) as usize];
+  let source = REGISTERS[(operation_code & 0x07) as usize];
+  let condition = CONDITIONS[(operation_code >> 3 & 0x07) as usize];
+  let pair = [RegisterPair::B, RegisterPair::D, RegisterPair::H, RegisterPair::SP][(operation_code >> 4 & 0x03) as usize];
+  let stack_pair = if pair == RegisterPair::SP { RegisterPair::PSW } else { pair };
+
+  match operation_code {
+    0x76 => Hlt,
+    0x40..=0x7f => Mov(destination, source),
+    0x80..=0xbf => match operation_code >> 3 & 0x07 {
+      0 => Add(source),
+      1 => Adc(source),
+      2 => Sub(source),
+      3 => Sbb(source),
+      4 => Ana(source),
+      5 => Xra(source),
+      6 => Ora(source),
+      _ => Cmp(source),
+    },
+    _ => match operation_code & 0xc7 {
+      0x04 => Inr(destination),
+      0x05 => Dcr(destination),
+      0x06 => Mvi(destination, byte),
+      0xc0 => Rcc(condition),
+      0xc2 => Jcc(condition, word),
+      0xc4 => Ccc(condition, word),
+      0xc7 => Rst(operation_code >> 3 & 0x07),
+      _ => match operation_code {
+        0x01 | 0x11 | 0x21 | 0x31 => Lxi(pair, word),
+        0x02 | 0x12 => Stax(pair),
+        0x03 | 0x13 | 0x23 | 0x33 => Inx(pair),
+        0x09 | 0x19 | 0x29 | 0x39 => Dad(pair),
+        0x0a | 0x1a => Ldax(pair),
+        0x0b | 0x1b | 0x2b | 0x3b => Dcx(pair),
+        0x07 => Rlc,
+        0x0f => Rrc,
+        0x17 => Ral,
+        0x1f => Rar,
+        0x22 => Shld(word),
+        0x27 => Daa,
+        0x2a => Lhld(word),
+        0x2f => Cma,
+        0x32 => Sta(word),
+        0x37 => Stc,
+        0x3a => Lda(word),
+        0x3f => Cmc,
+        0xc1 | 0xd1 | 0xe1 | 0xf1 => Pop(stack_pair),
+        0xc5 | 0xd5 | 0xe5 | 0xf5 => Push(stack_pair),
+        0xc3 | 0xcb => Jmp(word),
+        0xc9 | 0xd9 => Ret,
+        0xcd | 0xdd | 0xed | 0xfd => Call(word),
+        0xc6 => Adi(byte),
+        0xce => Aci(byte),
+        0xd3 => Out(byte),
+        0xd6 => Sui(byte),
+        0xdb => In(byte),
+        0xde => Sbi(byte),
+        0xe3 => Xthl,
+        0xe6 => Ani(byte),
+        0xe9 => Pchl,
+        0xeb => Xchg,
+        0xee => Xri(byte),
+        0xf3 => Di,
+        0xf6 => Ori(byte),
+        0xf9 => Sphl,
+        0xfb => Ei,
+        0xfe => Cpi(byte),
+        // 0x00 and its aliases 0x08 - 0x38
+        _ => Nop,
+      },
+    },
+  }
+}
+
+/// Iterator over the instructions of a byte slice, see `instructions`
+pub struct Instructions<'a> {
+  instruction_buffer: &'a [u8],
+  address: u16,
+  done: bool,
+}
+
+/// Decodes `instruction_buffer` from `address` to its end, yielding each
+/// instruction together with its address. Like `decode`, the buffer is
+/// addressed from 0.
+pub fn instructions<'a>(instruction_buffer: &'a [u8], address: u16) -> Instructions<'a> {
+  Instructions { instruction_buffer, address, done: false }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+  type Item = (u16, Instruction);
+
+  fn next(&mut self) -> Option<(u16, Instruction)> {
+    if self.done || self.address as usize >= self.instruction_buffer.len() {
+      return None;
+    }
+    let address = self.address;
+    let instruction = decode(self.instruction_buffer, address);
+    let (next, wrapped) = address.overflowing_add(instruction.size());
+    self.address = next;
+    self.done = wrapped;
+    Some((address, instruction))
+  }
+}
+
+impl Instruction {
+  /// The documented opcode of this instruction
+  pub fn opcode(&self) -> u8 {
+    use self::Instruction::*;
+
+    let r = |register: Register| register as u8;
+    let rp = |pair: RegisterPair| match pair { RegisterPair::PSW => 3, _ => pair as u8 } << 4;
+    let cc = |condition: Condition| (condition as u8) << 3;
+
+    match *self {
+      Nop => 0x00,
+      Lxi(pair, _) => 0x01 | rp(pair),
+      Stax(pair) => 0x02 | rp(pair),
+      Inx(pair) => 0x03 | rp(pair),
+      Inr(register) => 0x04 | r(register) << 3,
+      Dcr(register) => 0x05 | r(register) << 3,
+      Mvi(register, _) => 0x06 | r(register) << 3,
+      Rlc => 0x07,
+      Dad(pair) => 0x09 | rp(pair),
+      Ldax(pair) => 0x0a | rp(pair),
+      Dcx(pair) => 0x0b | rp(pair),
+      Rrc => 0x0f,
+      Ral => 0x17,
+      Rar => 0x1f,
+      Shld(_) => 0x22,
+      Daa => 0x27,
+      Lhld(_) => 0x2a,
+      Cma => 0x2f,
+      Sta(_) => 0x32,
+      Stc => 0x37,
+      Lda(_) => 0x3a,
+      Cmc => 0x3f,
+      Mov(destination, source) => 0x40 | r(destination) << 3 | r(source),
+      Hlt => 0x76,
+      Add(register) => 0x80 | r(register),
+      Adc(register) => 0x88 | r(register),
+      Sub(register) => 0x90 | r(register),
+      Sbb(register) => 0x98 | r(register),
+      Ana(register) => 0xa0 | r(register),
+      Xra(register) => 0xa8 | r(register),
+      Ora(register) => 0xb0 | r(register),
+      Cmp(register) => 0xb8 | r(register),
+      Rcc(condition) => 0xc0 | cc(condition),
+      Pop(pair) => 0xc1 | rp(pair),
+      Jcc(condition, _) => 0xc2 | cc(condition),
+      Jmp(_) => 0xc3,
+      Ccc(condition, _) => 0xc4 | cc(condition),
+      Push(pair) => 0xc5 | rp(pair),
+      Adi(_) => 0xc6,
+      Rst(n) => 0xc7 | (n & 0x07) << 3,
+      Ret => 0xc9,
+      Call(_) => 0xcd,
+      Aci(_) => 0xce,
+      Out(_) => 0xd3,
+      Sui(_) => 0xd6,
+      In(_) => 0xdb,
+      Sbi(_) => 0xde,
+      Xthl => 0xe3,
+      Ani(_) => 0xe6,
+      Pchl => 0xe9,
+      Xchg => 0xeb,
+      Xri(_) => 0xee,
+      Di => 0xf3,
+      Ori(_) => 0xf6,
+      Sphl => 0xf9,
+      Ei => 0xfb,
+      Cpi(_) => 0xfe,
+    }
+  }
+
+  /// Size in bytes, opcode included
+  pub fn size(&self) -> u16 {
+    OPCODES[self.opcode() as usize].size as u16
+  }
+
+  /// Mnemonic without operands, e.g. "MOV"
+  pub fn mnemonic(&self) -> &'static str {
+    OPCODES[self.opcode() as usize].mnemonic
+  }
+
+  /// Operands as written in assembly, e.g. "B, #$12", empty if there are none
+  fn operands(&self) -> String {
+    use self::Instruction::*;
+
+    match *self {
+      Lxi(pair, word) => format!("{}, #${:04x}", pair, word),
+      Mvi(register, byte) => format!("{}, #${:02x}", register, byte),
+      Mov(destination, source) => format!("{}, {}", destination, source),
+      Stax(pair) | Inx(pair) | Dad(pair) | Ldax(pair) | Dcx(pair) | Pop(pair) | Push(pair) => pair.to_string(),
+      Inr(register) | Dcr(register) | Add(register) | Adc(register) | Sub(register) | Sbb(register) |
+      Ana(register) | Xra(register) | Ora(register) | Cmp(register) => register.to_string(),
+      Shld(address) | Lhld(address) | Sta(address) | Lda(address) | Jcc(_, address) | Jmp(address) |
+      Ccc(_, address) | Call(address) => format!("${:04x}", address),
+      Adi(byte) | Aci(byte) | Out(byte) | Sui(byte) | In(byte) | Sbi(byte) | Ani(byte) | Xri(byte) |
+      Ori(byte) | Cpi(byte) => format!("#${:02x}", byte),
+      Rst(n) => n.to_string(),
+      _ => String::new(),
+    }
+  }
+}
+
+impl fmt::Display for Register {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(self, f)
+  }
+}
+
+impl fmt::Display for RegisterPair {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(self, f)
+  }
+}
+
+impl fmt::Display for Condition {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(self, f)
+  }
+}
+
+impl fmt::Display for Instruction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let operands = self.operands();
+    if operands.is_empty() {
+      write!(f, "{}", self.mnemonic())
+    } else {
+      write!(f, "{} {}", self.mnemonic(), operands)
+    }
+  }
+}
+
+/// Writes the instruction at `program_counter` as one line of 8080 assembly to
+/// `output_file` and returns its size in bytes. Operand bytes past the end of the
+/// buffer read as 0.
+pub fn disassemble<W: Write>(instruction_buffer: &[u8], program_counter: u16, output_file: &mut W) -> io::Result<u16> {
+  write_instruction(decode(instruction_buffer, program_counter), program_counter, output_file)
+}
+
+/// Like `disassemble`, reading the instruction through `bus` so mirrors and
+/// banks resolve the way the processor sees them
+pub fn disassemble_bus<B: Bus, W: Write>(bus: &B, program_counter: u16, output_file: &mut W) -> io::Result<u16> {
+  let instruction_buffer = [
+    bus.read(program_counter),
+    bus.read(program_counter.wrapping_add(1)),
+    bus.read(program_counter.wrapping_add(2)),
+  ];
+  write_instruction(decode(&instruction_buffer, 0), program_counter, output_file)
+}
+
+fn write_instruction<W: Write>(instruction: Instruction, program_counter: u16, output_file: &mut W) -> io::Result<u16> {
+  let operands = instruction.operands();
+
+  if operands.is_empty() {
+    writeln!(output_file, "{:04x}: \t{}", program_counter, instruction.mnemonic())?;
+  } else {
+    writeln!(output_file, "{:04x}: \t{} \t{}", program_counter, instruction.mnemonic(), operands)?;
+  }
+  Ok(instruction.size())
+}
+
+#[test]
+fn disassemble_test() {
+  let mut output = Vec::new();
+  let program = [0x2e, 0x12, 0xb0, 0xd0, 0x01, 0x34, 0x12, 0xd3, 0x01, 0xc3, 0x00, 0x20];
+  let mut program_counter = 0;
+  while (program_counter as usize) < program.len() {
+    program_counter += disassemble(&program, program_counter, &mut output).unwrap();
+  }
+  assert_eq!(String::from_utf8(output).unwrap(),
+    "0000: \tMVI \tL, #$12\n\
+     0002: \tORA \tB\n\
+     0003: \tRNC\n\
+     0004: \tLXI \tB, #$1234\n\
+     0007: \tOUT \t#$01\n\
+     0009: \tJMP \t$2000\n");
+}
+
+#[test]
+fn decode_test() {
+  // every opcode decodes to an instruction the table agrees with
+  for (opcode, info) in OPCODES.iter().enumerate() {
+    let instruction = decode(&[opcode as u8, 0x34, 0x12], 0);
+    if !info.undocumented {
+      assert_eq!(instruction.opcode() as usize, opcode);
+    }
+    assert_eq!(instruction.mnemonic(), info.mnemonic, "mnemonic of {:02x}", opcode);
+    assert_eq!(instruction.size(), info.size as u16, "size of {:02x}", opcode);
+    assert!(instruction.to_string().starts_with(info.mnemonic));
+    assert!(instruction.operands().starts_with(info.registers), "operands of {:02x}", opcode);
+  }
+
+  assert_eq!(decode(&[0x70], 0), Instruction::Mov(Register::M, Register::B));
+  assert_eq!(decode(&[0xf5], 0).to_string(), "PUSH PSW");
+  assert_eq!(decode(&[0xda, 0x00, 0x20], 0).to_string(), "JC $2000");
+
+  let program = [0x00, 0x3e, 0x01, 0xcd, 0x00, 0x01, 0x76];
+  let decoded: Vec<_> = instructions(&program, 1).collect();
+  assert_eq!(decoded, vec![
+    (0x0001, Instruction::Mvi(Register::A, 0x01)),
+    (0x0003, Instruction::Call(0x0100)),
+    (0x0006, Instruction::Hlt),
+  ]);
+}
@@ -0,0 +1,40 @@
+//! Why `emulate` could not execute an instruction
+
+use std::error::Error;
+use std::fmt;
+
+use bus::BusFault;
+use cpu::Registers;
+
+/// What went wrong, see `EmulateError`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+  /// The opcode is one of the undocumented aliases and the processor is in
+  /// strict mode. Nothing was executed, pc still points at the opcode.
+  IllegalOpcode(u8),
+  /// HLT with interrupts disabled, only a reset continues from here
+  Halted,
+  /// The instruction at `pc` accessed memory the bus could not serve. The
+  /// instruction has completed, the registers show its result.
+  BusFault { pc: u16, fault: BusFault },
+}
+
+/// Error returned by `emulate`, with a snapshot of the registers at the failure
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EmulateError {
+  pub kind: ErrorKind,
+  pub registers: Registers,
+}
+
+impl fmt::Display for EmulateError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.kind {
+      ErrorKind::IllegalOpcode(opcode) => write!(f, "illegal opcode {:02x}", opcode)?,
+      ErrorKind::Halted => write!(f, "halted with interrupts disabled")?,
+      ErrorKind::BusFault { pc, fault } => write!(f, "{} at {:04x}", fault, pc)?,
+    }
+    write!(f, " ({})", self.registers)
+  }
+}
+
+impl Error for EmulateError {}
@@ -0,0 +1,35 @@
+//! The IN and OUT ports: how the processor reaches port mapped devices
+
+use std::io::{self, Read, Write};
+
+/// Devices reached through the 256 ports of IN and OUT. A machine implements
+/// this to attach peripherals like shift registers, sound latches or serial cards.
+pub trait Io {
+  /// Value the device on `port` puts on the data bus for IN
+  fn input(&mut self, port: u8) -> u8;
+
+  /// OUT wrote `value` to `port`
+  fn output(&mut self, port: u8, value: u8);
+
+  /// Writes the state of the devices for a save state
+  fn save_state(&self, _writer: &mut dyn Write) -> io::Result<()> {
+    Ok(())
+  }
+
+  /// Restores what `save_state` wrote
+  fn load_state(&mut self, _reader: &mut dyn Read) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// No devices attached, the default `Io`: IN reads 0 and OUT is ignored
+pub struct NullIo;
+
+impl Io for NullIo {
+  fn input(&mut self, _port: u8) -> u8 {
+    0x00
+  }
+
+  fn output(&mut self, _port: u8, _value: u8) {
+  }
+}
@@ -0,0 +1,36 @@
+//! Intel 8080 CPU core shared by the emulator and the disassembler.
+//!
+//! ```
+//! let mut cpu_state = i8080::init_cpu();
+//! cpu_state.memory[0] = 0x76;  // HLT
+//! assert_eq!(i8080::emulate(&mut cpu_state), Ok(7));
+//! ```
+//!
+//! Machines with their own memory layout implement `Bus`, their port mapped
+//! devices implement `Io`; both are handed to `init_cpu_with`.
+
+pub mod bus;
+pub mod cpm;
+pub mod cpu;
+pub mod disassembler;
+pub mod error;
+pub mod io;
+pub mod loader;
+pub mod memory_map;
+pub mod movie;
+pub mod opcodes;
+pub mod rewind;
+pub mod savestate;
+
+pub use bus::{Bus, BusFault, FlatMemory};
+pub use cpm::{BdosError, BufferConsole, Console, Cpm};
+pub use cpu::{CpuState, ConditionCode, Registers, init_cpu, init_cpu_with, emulate, registers, interrupt, interrupt_rst, parity};
+pub use disassembler::{Instruction, Instructions, Register, RegisterPair, Condition, decode, disassemble, disassemble_bus, instructions};
+pub use error::{EmulateError, ErrorKind};
+pub use io::{Io, NullIo};
+pub use loader::{Image, LoadError, Segment};
+pub use memory_map::{BankLatch, BankRegister, MemoryMap, Region, RegionKind};
+pub use movie::{Movie, MovieIo};
+pub use opcodes::{OpcodeInfo, Operand, OPCODES};
+pub use rewind::{Journal, Rewind};
+pub use savestate::{save_state, load_state};
@@ -0,0 +1,273 @@
+//! Program loaders: Intel HEX, Motorola S-records and raw binaries
+//!
+//! Each loader produces an `Image`, the bytes to place in memory grouped in
+//! segments, and the start address if the file names one. Images refuse bytes
+//! that overlap bytes already in them.
+
+use std::error::Error;
+use std::fmt;
+
+/// Bytes to place at consecutive addresses from `address` on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+  pub address: u16,
+  pub data: Vec<u8>,
+}
+
+/// A program ready to place in memory
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Image {
+  pub segments: Vec<Segment>,
+  /// Where execution starts, if the file says so
+  pub start: Option<u16>,
+}
+
+/// Why a program could not be loaded; `line` counts from 1, 0 when the
+/// error is not about a line
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadError {
+  /// The line is not a valid record
+  Syntax { line: usize, message: String },
+  /// The record checksum does not match its contents
+  Checksum { line: usize, expected: u8, actual: u8 },
+  /// Data beyond the 64 KiB the 8080 addresses
+  OutOfRange { line: usize, address: u32 },
+  /// A byte at `address` is loaded twice
+  Overlap { address: u16 },
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      LoadError::Syntax { line, ref message } => write!(f, "line {}: {}", line, message),
+      LoadError::Checksum { line, expected, actual } =>
+        write!(f, "line {}: checksum {:02x}, expected {:02x}", line, actual, expected),
+      LoadError::OutOfRange { line: 0, address } => write!(f, "address {:x} beyond 64 KiB", address),
+      LoadError::OutOfRange { line, address } => write!(f, "line {}: address {:x} beyond 64 KiB", line, address),
+      LoadError::Overlap { address } => write!(f, "{:04x} is loaded twice", address),
+    }
+  }
+}
+
+impl Error for LoadError {}
+
+impl Image {
+  pub fn new() -> Image {
+    Image::default()
+  }
+
+  /// Adds `data` at `address`, failing on bytes already in the image or past 0xffff
+  pub fn add(&mut self, address: u16, data: &[u8]) -> Result<(), LoadError> {
+    if data.is_empty() {
+      return Ok(());
+    }
+    let end = address as u32 + data.len() as u32;
+    if end > 0x10000 {
+      return Err(LoadError::OutOfRange { line: 0, address: end - 1 });
+    }
+    for segment in &self.segments {
+      let segment_end = segment.address as u32 + segment.data.len() as u32;
+      if (address as u32) < segment_end && (segment.address as u32) < end {
+        return Err(LoadError::Overlap { address: address.max(segment.address) });
+      }
+    }
+
+    // records usually follow each other, keep them in one segment
+    if let Some(last) = self.segments.last_mut() {
+      if last.address as u32 + last.data.len() as u32 == address as u32 {
+        last.data.extend_from_slice(data);
+        return Ok(());
+      }
+    }
+    self.segments.push(Segment { address, data: data.to_vec() });
+    Ok(())
+  }
+
+  /// Adds all segments of `other`, taking its start address if it has one
+  pub fn merge(&mut self, other: Image) -> Result<(), LoadError> {
+    for segment in other.segments {
+      self.add(segment.address, &segment.data)?;
+    }
+    self.start = other.start.or(self.start);
+    Ok(())
+  }
+
+  /// A raw binary loaded at `address`
+  pub fn from_binary(address: u16, data: &[u8]) -> Result<Image, LoadError> {
+    let mut image = Image::new();
+    image.add(address, data)?;
+    Ok(image)
+  }
+
+  /// Parses Intel HEX: data, end of file, start address and extended address
+  /// records, the latter only as long as the data stays below 64 KiB
+  pub fn from_intel_hex(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image::new();
+    let mut base: u32 = 0;
+
+    for (index, line) in text.lines().enumerate() {
+      let line_number = index + 1;
+      let line = line.trim();
+      if line.is_empty() {
+        continue;
+      }
+      if !line.starts_with(':') {
+        return Err(syntax(line_number, "record does not start with ':'"));
+      }
+      let bytes = hex_bytes(&line[1..], line_number)?;
+      if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+        return Err(syntax(line_number, "record length does not match its byte count"));
+      }
+
+      let sum = bytes[..bytes.len() - 1].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+      let expected = 0u8.wrapping_sub(sum);
+      let actual = bytes[bytes.len() - 1];
+      if expected != actual {
+        return Err(LoadError::Checksum { line: line_number, expected, actual });
+      }
+
+      let offset = (bytes[1] as u32) << 8 | bytes[2] as u32;
+      let data = &bytes[4..bytes.len() - 1];
+      match bytes[3] {
+        0x00 => add_at(&mut image, base + offset, data, line_number)?,
+        0x01 => break,
+        0x02 if data.len() == 2 => base = ((data[0] as u32) << 8 | data[1] as u32) << 4,
+        0x04 if data.len() == 2 => base = ((data[0] as u32) << 8 | data[1] as u32) << 16,
+        0x03 | 0x05 if data.len() == 4 => {
+          let word = |at: usize| (data[at] as u32) << 8 | data[at + 1] as u32;
+          // CS:IP for type 03, a linear address for type 05
+          let start = if bytes[3] == 0x03 { (word(0) << 4) + word(2) } else { word(0) << 16 | word(2) };
+          if start > 0xffff {
+            return Err(LoadError::OutOfRange { line: line_number, address: start });
+          }
+          image.start = Some(start as u16);
+        },
+        kind => return Err(syntax(line_number, &format!("unsupported record type {:02x}", kind))),
+      }
+    }
+    Ok(image)
+  }
+
+  /// Parses Motorola S-records: S1, S2 and S3 data, S7, S8 and S9 start
+  /// addresses; the S0 header and S5/S6 counts are skipped
+  pub fn from_srecord(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image::new();
+
+    for (index, line) in text.lines().enumerate() {
+      let line_number = index + 1;
+      let line = line.trim();
+      if line.is_empty() {
+        continue;
+      }
+      if line.len() < 2 || !line.starts_with('S') {
+        return Err(syntax(line_number, "record does not start with 'S'"));
+      }
+      let kind = line.as_bytes()[1];
+      let digits = line.get(2..).ok_or_else(|| syntax(line_number, "invalid record type"))?;
+      let bytes = hex_bytes(digits, line_number)?;
+      if bytes.len() < 2 || bytes.len() != bytes[0] as usize + 1 {
+        return Err(syntax(line_number, "record length does not match its byte count"));
+      }
+
+      let sum = bytes[..bytes.len() - 1].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+      let expected = !sum;
+      let actual = bytes[bytes.len() - 1];
+      if expected != actual {
+        return Err(LoadError::Checksum { line: line_number, expected, actual });
+      }
+
+      let address_size = match kind {
+        b'0' | b'1' | b'5' | b'9' => 2,
+        b'2' | b'6' | b'8' => 3,
+        b'3' | b'7' => 4,
+        _ => return Err(syntax(line_number, &format!("unsupported record type S{}", kind as char))),
+      };
+      if bytes.len() < address_size + 2 {
+        return Err(syntax(line_number, "record too short for its address"));
+      }
+      let address = bytes[1..1 + address_size].iter().fold(0u32, |address, &byte| address << 8 | byte as u32);
+      let data = &bytes[1 + address_size..bytes.len() - 1];
+      match kind {
+        b'1' | b'2' | b'3' => add_at(&mut image, address, data, line_number)?,
+        b'7' | b'8' | b'9' => {
+          if address > 0xffff {
+            return Err(LoadError::OutOfRange { line: line_number, address });
+          }
+          image.start = Some(address as u16);
+        },
+        _ => {},
+      }
+    }
+    Ok(image)
+  }
+}
+
+fn add_at(image: &mut Image, address: u32, data: &[u8], line: usize) -> Result<(), LoadError> {
+  if address + data.len() as u32 > 0x10000 {
+    return Err(LoadError::OutOfRange { line, address: address + data.len() as u32 - 1 });
+  }
+  image.add(address as u16, data)
+}
+
+fn syntax(line: usize, message: &str) -> LoadError {
+  LoadError::Syntax { line, message: message.to_string() }
+}
+
+// is_multiple_of needs Rust 1.87
+#[allow(clippy::manual_is_multiple_of)]
+fn hex_bytes(digits: &str, line: usize) -> Result<Vec<u8>, LoadError> {
+  if digits.len() % 2 != 0 {
+    return Err(syntax(line, "odd number of hex digits"));
+  }
+  (0..digits.len()).step_by(2)
+    .map(|at| digits.get(at..at + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()).ok_or_else(|| syntax(line, "invalid hex digit")))
+    .collect()
+}
+
+#[test]
+fn intel_hex_test() {
+  let image = Image::from_intel_hex("
+    :03000000310024A8
+    :020100003E0EB1
+    :02010200D30127
+    :0400000500000100F6
+    :00000001FF
+  ").unwrap();
+  assert_eq!(image.segments, vec![
+    Segment { address: 0x0000, data: vec![0x31, 0x00, 0x24] },
+    Segment { address: 0x0100, data: vec![0x3e, 0x0e, 0xd3, 0x01] },
+  ]);
+  assert_eq!(image.start, Some(0x0100));
+  // CS:IP 0011:0010 is 0x0120, IP adds to the segment
+  assert_eq!(Image::from_intel_hex(":0400000300110010D8").unwrap().start, Some(0x0120));
+
+  assert_eq!(Image::from_intel_hex(":03000000310024A9"), Err(LoadError::Checksum { line: 1, expected: 0xa8, actual: 0xa9 }));
+  assert_eq!(Image::from_intel_hex(":03000000310024"), Err(LoadError::Syntax { line: 1, message: "record length does not match its byte count".to_string() }));
+  assert_eq!(Image::from_intel_hex(":03000000310024A8\n:020001003E0EB1"), Err(LoadError::Overlap { address: 0x0001 }));
+  assert_eq!(Image::from_intel_hex(":020000040001F9\n:0100000000FF"), Err(LoadError::OutOfRange { line: 2, address: 0x10000 }));
+}
+
+#[test]
+fn srecord_test() {
+  let image = Image::from_srecord("
+    S00600004844521B
+    S1060100310024A3
+    S5030001FB
+    S9030100FB
+  ").unwrap();
+  assert_eq!(image.segments, vec![Segment { address: 0x0100, data: vec![0x31, 0x00, 0x24] }]);
+  assert_eq!(image.start, Some(0x0100));
+
+  assert_eq!(Image::from_srecord("S1060100310024A4"), Err(LoadError::Checksum { line: 1, expected: 0xa3, actual: 0xa4 }));
+  assert!(Image::from_srecord("X1060100").is_err());
+  assert_eq!(Image::from_srecord("S\u{e9}060100"), Err(LoadError::Syntax { line: 1, message: "invalid record type".to_string() }));
+}
+
+#[test]
+fn image_test() {
+  let mut image = Image::from_binary(0x0100, &[1, 2, 3]).unwrap();
+  assert_eq!(image.merge(Image::from_binary(0x0102, &[4]).unwrap()), Err(LoadError::Overlap { address: 0x0102 }));
+  image.merge(Image::from_binary(0x0103, &[4]).unwrap()).unwrap();
+  assert_eq!(image.segments, vec![Segment { address: 0x0100, data: vec![1, 2, 3, 4] }]);
+  assert_eq!(Image::from_binary(0xffff, &[1, 2]), Err(LoadError::OutOfRange { line: 0, address: 0x10000 }));
+}
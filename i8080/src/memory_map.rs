@@ -0,0 +1,404 @@
+//! A `Bus` laid out from regions: ROM, RAM, banked windows and mirrors of other regions
+
+use std::cell::Cell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use bus::{Bus, BusFault};
+use io::Io;
+
+/// What a region of the address space is
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionKind {
+  /// Read only, writes are ignored or reported, see `MemoryMap::report_rom_writes`
+  Rom,
+  /// Readable and writable
+  Ram,
+  /// Resolves each address to `base + (address - start) % size`
+  Mirror { base: u16, size: u16 },
+  /// RAM window showing one of several banks, the `window`th banked region
+  Banked { window: usize },
+}
+
+/// A named range of addresses, `start` and `end` inclusive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+  pub name: &'static str,
+  pub start: u16,
+  pub end: u16,
+  pub kind: RegionKind,
+}
+
+impl fmt::Display for Region {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.kind {
+      RegionKind::Rom => write!(f, "{:04x}-{:04x} ROM {}", self.start, self.end, self.name),
+      RegionKind::Ram => write!(f, "{:04x}-{:04x} RAM {}", self.start, self.end, self.name),
+      RegionKind::Mirror { base, size } =>
+        write!(f, "{:04x}-{:04x} mirror of {:04x}-{:04x} {}", self.start, self.end, base, base as u32 + size as u32 - 1, self.name),
+      RegionKind::Banked { .. } => write!(f, "{:04x}-{:04x} banked RAM {}", self.start, self.end, self.name),
+    }
+  }
+}
+
+/// Selects the bank a banked window shows. Clones share the value, so the
+/// port device the program switches banks with can hold one, see `BankLatch`.
+#[derive(Clone, Debug, Default)]
+pub struct BankRegister(Rc<Cell<u8>>);
+
+impl BankRegister {
+  /// Selecting bank 0
+  pub fn new() -> BankRegister {
+    BankRegister::default()
+  }
+
+  pub fn get(&self) -> u8 {
+    self.0.get()
+  }
+
+  pub fn set(&self, bank: u8) {
+    self.0.set(bank);
+  }
+}
+
+/// The banks behind one banked window
+struct Window {
+  register: BankRegister,
+  banks: Vec<Vec<u8>>,
+}
+
+/// Memory laid out from `Region`s. Unmapped addresses read as 0xff, accesses
+/// to them are reported through `take_fault` like writes to ROM can be.
+pub struct MemoryMap {
+  regions: Vec<Region>,
+  bytes: Vec<u8>,
+  windows: Vec<Window>,
+  /// Report writes to ROM as `BusFault::WriteProtected` instead of ignoring them
+  pub report_rom_writes: bool,
+  fault: Cell<Option<BusFault>>,
+}
+
+impl MemoryMap {
+  /// Nothing mapped
+  pub fn new() -> MemoryMap {
+    MemoryMap { regions: Vec::new(), bytes: vec![0; 0x10000], windows: Vec::new(), report_rom_writes: false, fault: Cell::new(None) }
+  }
+
+  /// Maps read only memory to `start`-`end`
+  pub fn map_rom(&mut self, name: &'static str, start: u16, end: u16) {
+    self.map(Region { name, start, end, kind: RegionKind::Rom });
+  }
+
+  /// Maps read write memory to `start`-`end`
+  pub fn map_ram(&mut self, name: &'static str, start: u16, end: u16) {
+    self.map(Region { name, start, end, kind: RegionKind::Ram });
+  }
+
+  /// Maps `start`-`end` to repeat the `size` addresses from `base` on,
+  /// which must be mapped to ROM or RAM
+  pub fn map_mirror(&mut self, name: &'static str, start: u16, end: u16, base: u16, size: u16) {
+    assert!(size > 0, "mirror {} of nothing", name);
+    self.map(Region { name, start, end, kind: RegionKind::Mirror { base, size } });
+  }
+
+  /// Maps a RAM window to `start`-`end` that shows one of `banks` banks,
+  /// the one `register` selects. Selecting a bank that does not exist
+  /// leaves the window unmapped.
+  pub fn map_banked(&mut self, name: &'static str, start: u16, end: u16, banks: usize, register: BankRegister) {
+    let size = (end as usize).saturating_sub(start as usize) + 1;
+    self.map(Region { name, start, end, kind: RegionKind::Banked { window: self.windows.len() } });
+    self.windows.push(Window { register, banks: vec![vec![0; size]; banks] });
+  }
+
+  /// Copies `data` into `bank` of the banked window at `address` and on,
+  /// no matter which bank is selected
+  pub fn load_bank(&mut self, address: u16, bank: usize, data: &[u8]) {
+    let (start, window) = match self.region(address) {
+      Some(&Region { start, kind: RegionKind::Banked { window }, .. }) => (start, window),
+      _ => panic!("no banked window at {:04x}", address),
+    };
+    let bytes = &mut self.windows[window].banks[bank];
+    let offset = (address - start) as usize;
+    let length = data.len().min(bytes.len() - offset);
+    bytes[offset..offset + length].copy_from_slice(&data[..length]);
+  }
+
+  /// The bank the window at `address` shows, None if `address` is not banked
+  pub fn active_bank(&self, address: u16) -> Option<u8> {
+    match self.region(address)?.kind {
+      RegionKind::Banked { window } => Some(self.windows[window].register.get()),
+      _ => None,
+    }
+  }
+
+  fn map(&mut self, region: Region) {
+    assert!(region.start <= region.end, "region {} ends before it starts", region.name);
+    if let Some(other) = self.regions.iter().find(|other| region.start <= other.end && other.start <= region.end) {
+      panic!("region {} overlaps {}", region.name, other.name);
+    }
+    self.regions.push(region);
+    self.regions.sort_by_key(|region| region.start);
+  }
+
+  /// The mapped regions, ordered by address
+  pub fn regions(&self) -> &[Region] {
+    &self.regions
+  }
+
+  /// The region `address` belongs to
+  pub fn region(&self, address: u16) -> Option<&Region> {
+    self.regions.iter().find(|region| region.start <= address && address <= region.end)
+  }
+
+  /// Copies `data` to `address` and on, ROM included. This is how ROM gets its
+  /// contents; banked windows get it in their selected bank.
+  pub fn load(&mut self, address: u16, data: &[u8]) {
+    for (offset, &byte) in data.iter().enumerate() {
+      if let Some((location, _)) = self.resolve(address.wrapping_add(offset as u16)) {
+        *self.location_mut(location) = byte;
+      }
+    }
+  }
+
+  /// where the byte at `address` lives and what kind of region it belongs to, mirrors followed
+  fn resolve(&self, address: u16) -> Option<(Location, RegionKind)> {
+    let mut region = self.region(address)?;
+    let mut address = address;
+    if let RegionKind::Mirror { base, size } = region.kind {
+      address = base.wrapping_add(((address - region.start) as u32 % size as u32) as u16);
+      region = self.region(address)?;
+    }
+    match region.kind {
+      RegionKind::Mirror { .. } => None,
+      RegionKind::Banked { window } => {
+        let bank = self.windows[window].register.get() as usize;
+        if bank < self.windows[window].banks.len() {
+          Some((Location::Bank(window, bank, (address - region.start) as usize), region.kind))
+        } else {
+          None
+        }
+      },
+      kind => Some((Location::Flat(address as usize), kind)),
+    }
+  }
+
+  fn location(&self, location: Location) -> u8 {
+    match location {
+      Location::Flat(index) => self.bytes[index],
+      Location::Bank(window, bank, index) => self.windows[window].banks[bank][index],
+    }
+  }
+
+  fn location_mut(&mut self, location: Location) -> &mut u8 {
+    match location {
+      Location::Flat(index) => &mut self.bytes[index],
+      Location::Bank(window, bank, index) => &mut self.windows[window].banks[bank][index],
+    }
+  }
+
+  fn latch(&self, fault: BusFault) {
+    if self.fault.get().is_none() {
+      self.fault.set(Some(fault));
+    }
+  }
+}
+
+/// a byte of the map: in the flat address space or in a bank of a window
+#[derive(Clone, Copy)]
+enum Location {
+  Flat(usize),
+  Bank(usize, usize, usize),
+}
+
+impl Default for MemoryMap {
+  fn default() -> MemoryMap {
+    MemoryMap::new()
+  }
+}
+
+impl Bus for MemoryMap {
+  fn read(&self, address: u16) -> u8 {
+    match self.resolve(address) {
+      Some((location, _)) => self.location(location),
+      None => { self.latch(BusFault::UnmappedRead(address)); 0xff },
+    }
+  }
+
+  fn write(&mut self, address: u16, value: u8) {
+    match self.resolve(address) {
+      Some((location, RegionKind::Ram)) | Some((location, RegionKind::Banked { .. })) => *self.location_mut(location) = value,
+      Some(_) => if self.report_rom_writes { self.latch(BusFault::WriteProtected(address)) },
+      None => self.latch(BusFault::UnmappedWrite(address)),
+    }
+  }
+
+  fn banks(&self) -> Vec<u8> {
+    self.windows.iter().map(|window| window.register.get()).collect()
+  }
+
+  fn set_banks(&mut self, banks: &[u8]) {
+    for (window, &bank) in self.windows.iter().zip(banks) {
+      window.register.set(bank);
+    }
+  }
+
+  fn take_fault(&mut self) -> Option<BusFault> {
+    self.fault.take()
+  }
+
+  // RAM and the banks with their selection, the machine loads the ROM itself
+  fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    for region in self.regions.iter().filter(|region| region.kind == RegionKind::Ram) {
+      writer.write_all(&self.bytes[region.start as usize..=region.end as usize])?;
+    }
+    for window in &self.windows {
+      writer.write_all(&[window.register.get()])?;
+      for bank in &window.banks {
+        writer.write_all(bank)?;
+      }
+    }
+    Ok(())
+  }
+
+  fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    for region in self.regions.iter().filter(|region| region.kind == RegionKind::Ram) {
+      reader.read_exact(&mut self.bytes[region.start as usize..=region.end as usize])?;
+    }
+    for window in &mut self.windows {
+      let mut selected = [0; 1];
+      reader.read_exact(&mut selected)?;
+      window.register.set(selected[0]);
+      for bank in &mut window.banks {
+        reader.read_exact(bank)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Display for MemoryMap {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for region in &self.regions {
+      match region.kind {
+        RegionKind::Banked { window } => writeln!(f, "{}, bank {} of {}", region, self.windows[window].register.get(), self.windows[window].banks.len())?,
+        _ => writeln!(f, "{}", region)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Port device that switches banks: OUT to `port` selects the bank of the
+/// `BankRegister`, IN from `port` reads it back. Everything else goes to `inner`.
+pub struct BankLatch<P> {
+  inner: P,
+  port: u8,
+  register: BankRegister,
+}
+
+impl<P: Io> BankLatch<P> {
+  pub fn new(inner: P, port: u8, register: BankRegister) -> BankLatch<P> {
+    BankLatch { inner, port, register }
+  }
+
+  /// The wrapped devices
+  pub fn inner_mut(&mut self) -> &mut P {
+    &mut self.inner
+  }
+}
+
+// the selected bank is saved with the memory map
+impl<P: Io> Io for BankLatch<P> {
+  fn input(&mut self, port: u8) -> u8 {
+    if port == self.port { self.register.get() } else { self.inner.input(port) }
+  }
+
+  fn output(&mut self, port: u8, value: u8) {
+    if port == self.port { self.register.set(value) } else { self.inner.output(port, value) }
+  }
+
+  fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    self.inner.save_state(writer)
+  }
+
+  fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    self.inner.load_state(reader)
+  }
+}
+
+#[test]
+fn memory_map_test() {
+  let mut memory = MemoryMap::new();
+  memory.map_rom("rom", 0x0000, 0x1fff);
+  memory.map_ram("work ram", 0x2000, 0x23ff);
+  memory.map_ram("video ram", 0x2400, 0x3fff);
+  memory.map_mirror("ram mirror", 0x4000, 0x5fff, 0x2000, 0x2000);
+  memory.load(0x0000, &[0xc3, 0x00, 0x20]);
+
+  assert_eq!(memory.read(0x0000), 0xc3);
+  assert_eq!(memory.region(0x2400).map(|region| region.name), Some("video ram"));
+
+  // ROM ignores writes unless asked to report them
+  memory.write(0x0001, 0x55);
+  assert_eq!(memory.read(0x0001), 0x00);
+  assert_eq!(memory.take_fault(), None);
+  memory.report_rom_writes = true;
+  memory.write(0x0001, 0x55);
+  assert_eq!(memory.take_fault(), Some(BusFault::WriteProtected(0x0001)));
+  assert_eq!(memory.read(0x0001), 0x00);
+
+  // mirrors resolve to their base
+  memory.write(0x4010, 0x42);
+  assert_eq!(memory.read(0x2010), 0x42);
+  memory.write(0x3fff, 0x24);
+  assert_eq!(memory.read(0x5fff), 0x24);
+
+  assert_eq!(memory.read(0x6000), 0xff);
+  memory.write(0x7000, 0x00);
+  assert_eq!(memory.take_fault(), Some(BusFault::UnmappedRead(0x6000)));
+  assert_eq!(memory.take_fault(), None);
+
+  let mut state = Vec::new();
+  memory.save_state(&mut state).unwrap();
+  assert_eq!(state.len(), 0x2000);
+}
+
+#[test]
+fn bank_test() {
+  use cpu::{init_cpu_with, emulate};
+  use io::NullIo;
+  use savestate::{save_state, load_state};
+
+  let register = BankRegister::new();
+  let mut memory = MemoryMap::new();
+  memory.map_banked("banked", 0x0000, 0xbfff, 3, register.clone());
+  memory.map_ram("common", 0xc000, 0xffff);
+  memory.load_bank(0x0100, 1, &[0x3e, 0x11]);  // MVI A, $11 in bank 1
+  assert_eq!(memory.active_bank(0x1234), Some(0));
+  assert_eq!(memory.active_bank(0xc000), None);
+
+  // common memory: MVI A,1; OUT $40; then jump into the window
+  memory.load(0xc000, &[0x3e, 0x01, 0xd3, 0x40, 0xc3, 0x00, 0x01]);
+  memory.load(0x0100, &[0x3e, 0x22]);  // MVI A, $22 in bank 0
+
+  let mut cpu_state = init_cpu_with(memory, BankLatch::new(NullIo, 0x40, register.clone()));
+  cpu_state.pc = 0xc000;
+  for _ in 0..4 {
+    emulate(&mut cpu_state).unwrap();
+  }
+  assert_eq!(cpu_state.a, 0x11);
+  assert_eq!(cpu_state.memory.active_bank(0x0000), Some(1));
+  assert!(cpu_state.memory.to_string().contains("bank 1 of 3"));
+
+  let mut state = Vec::new();
+  save_state(&cpu_state, &mut state).unwrap();
+  register.set(0);
+  load_state(&mut cpu_state, &mut &state[..]).unwrap();
+  assert_eq!(cpu_state.io.input(0x40), 1);
+
+  // a bank that does not exist is unmapped
+  register.set(7);
+  assert_eq!(cpu_state.memory.read(0x0100), 0xff);
+  assert_eq!(cpu_state.memory.take_fault(), Some(BusFault::UnmappedRead(0x0100)));
+}
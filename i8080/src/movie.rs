@@ -0,0 +1,306 @@
+//! Movies: record the inputs of a session and replay them bit-exactly
+//!
+//! Everything that reaches the processor from outside is either a value read
+//! by IN or an interrupt. `MovieIo` wraps the port devices and records both,
+//! keyed by the cycle count at the start of the instruction; since every
+//! instruction takes at least 4 T-states the cycle count names one instruction
+//! boundary. Replaying the movie from the same start state, reset or a save
+//! state, feeds back the same values at the same instructions.
+//!
+//! The run loop calls `sync` before every instruction and raises the machine's
+//! interrupts through `raise_interrupt`.
+
+use std::io::{self, Read, Write};
+
+use bus::Bus;
+use cpu::{CpuState, interrupt};
+use io::Io;
+use savestate::{read_u8, read_u64, write_u64};
+
+/// First bytes of every movie file
+pub const MAGIC: &[u8; 8] = b"I8080MOV";
+
+/// Movie format version, bumped whenever the layout changes
+pub const VERSION: u16 = 1;
+
+/// Something that reached the processor from outside
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+  /// IN from `port` read `value`
+  Input { cycles: u64, port: u8, value: u8 },
+  /// The machine raised an interrupt with `instruction` on the data bus
+  Interrupt { cycles: u64, instruction: [u8; 3] },
+}
+
+impl Event {
+  /// Cycle count at the start of the instruction the event belongs to
+  pub fn cycles(&self) -> u64 {
+    match *self {
+      Event::Input { cycles, .. } | Event::Interrupt { cycles, .. } => cycles,
+    }
+  }
+}
+
+/// The recorded events of a session, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Movie {
+  pub events: Vec<Event>,
+}
+
+impl Movie {
+  /// Writes the movie file
+  pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    for event in &self.events {
+      match *event {
+        Event::Input { cycles, port, value } => {
+          writer.write_all(&[0])?;
+          write_u64(writer, cycles)?;
+          writer.write_all(&[port, value])?;
+        },
+        Event::Interrupt { cycles, instruction } => {
+          writer.write_all(&[1])?;
+          write_u64(writer, cycles)?;
+          writer.write_all(&instruction)?;
+        },
+      }
+    }
+    Ok(())
+  }
+
+  /// Reads a movie file written by `save`
+  pub fn load<R: Read>(reader: &mut R) -> io::Result<Movie> {
+    let mut header = [0; 10];
+    reader.read_exact(&mut header)?;
+    if &header[..8] != MAGIC {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, "not a movie"));
+    }
+    let version = u16::from_le_bytes([header[8], header[9]]);
+    if version != VERSION {
+      return Err(io::Error::new(io::ErrorKind::InvalidData,
+        format!("movie version {} is not supported, expected {}", version, VERSION)));
+    }
+
+    let mut events = Vec::new();
+    let mut tag = [0; 1];
+    while reader.read(&mut tag)? == 1 {
+      let cycles = read_u64(reader)?;
+      match tag[0] {
+        0 => {
+          let port = read_u8(reader)?;
+          let value = read_u8(reader)?;
+          events.push(Event::Input { cycles, port, value });
+        },
+        1 => {
+          let mut instruction = [0; 3];
+          reader.read_exact(&mut instruction)?;
+          events.push(Event::Interrupt { cycles, instruction });
+        },
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown movie event {}", tag[0]))),
+      }
+    }
+    Ok(Movie { events })
+  }
+}
+
+enum Mode {
+  Off,
+  Record,
+  Replay { next: usize },
+}
+
+/// An `Io` that records or replays a `Movie` around the machine's devices
+pub struct MovieIo<P> {
+  inner: P,
+  movie: Movie,
+  mode: Mode,
+  cycles: u64,
+  desync: Option<u64>,
+}
+
+impl<P: Io> MovieIo<P> {
+  /// Passes everything through to `inner` until recording or replay starts
+  pub fn new(inner: P) -> MovieIo<P> {
+    MovieIo { inner, movie: Movie::default(), mode: Mode::Off, cycles: 0, desync: None }
+  }
+
+  /// The wrapped devices
+  pub fn inner(&self) -> &P {
+    &self.inner
+  }
+
+  /// The wrapped devices
+  pub fn inner_mut(&mut self) -> &mut P {
+    &mut self.inner
+  }
+
+  /// Starts recording a new movie
+  pub fn start_recording(&mut self) {
+    self.movie = Movie::default();
+    self.mode = Mode::Record;
+  }
+
+  /// Starts replaying `movie`. IN reads and interrupts then come from the
+  /// movie instead of the devices and the machine.
+  pub fn start_replay(&mut self, movie: Movie) {
+    self.movie = movie;
+    self.mode = Mode::Replay { next: 0 };
+    self.desync = None;
+  }
+
+  /// Stops recording or replay and hands out the movie
+  pub fn take_movie(&mut self) -> Movie {
+    self.mode = Mode::Off;
+    ::std::mem::take(&mut self.movie)
+  }
+
+  /// True while replaying and events are left
+  pub fn replaying(&self) -> bool {
+    match self.mode {
+      Mode::Replay { next } => next < self.movie.events.len(),
+      _ => false,
+    }
+  }
+
+  /// Cycle count of the first instruction that did not match the movie, if any.
+  /// From there on replay falls back to the devices.
+  pub fn desync(&self) -> Option<u64> {
+    self.desync
+  }
+
+  /// the next replayed event if it belongs to the current instruction
+  fn next_event(&mut self) -> Option<Event> {
+    if let Mode::Replay { ref mut next } = self.mode {
+      if self.desync.is_none() {
+        if let Some(&event) = self.movie.events.get(*next) {
+          if event.cycles() == self.cycles {
+            *next += 1;
+            return Some(event);
+          }
+          if event.cycles() < self.cycles {
+            self.desync = Some(self.cycles);
+          }
+        }
+      }
+    }
+    None
+  }
+}
+
+impl<P: Io> Io for MovieIo<P> {
+  fn input(&mut self, port: u8) -> u8 {
+    let value = self.inner.input(port);
+    match self.mode {
+      Mode::Off => value,
+      Mode::Record => {
+        self.movie.events.push(Event::Input { cycles: self.cycles, port, value });
+        value
+      },
+      Mode::Replay { .. } => match self.next_event() {
+        Some(Event::Input { port: recorded_port, value: recorded, .. }) if recorded_port == port => recorded,
+        _ => {
+          self.desync.get_or_insert(self.cycles);
+          value
+        },
+      },
+    }
+  }
+
+  fn output(&mut self, port: u8, value: u8) {
+    self.inner.output(port, value);
+  }
+
+  fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    self.inner.save_state(writer)
+  }
+
+  fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    self.inner.load_state(reader)
+  }
+}
+
+/// Call before every instruction: keys the movie to the cycle count and,
+/// when replaying, raises the interrupts recorded at this point
+pub fn sync<M: Bus, P: Io>(cpu_state: &mut CpuState<M, MovieIo<P>>) {
+  cpu_state.io.cycles = cpu_state.cycles;
+  while let Some(Event::Interrupt { instruction, .. }) = peek_interrupt(&mut cpu_state.io) {
+    interrupt(cpu_state, instruction);
+  }
+}
+
+/// the recorded interrupt at the current instruction, inputs stay for IN
+fn peek_interrupt<P: Io>(movie_io: &mut MovieIo<P>) -> Option<Event> {
+  let is_interrupt = match movie_io.mode {
+    Mode::Replay { next } => matches!(movie_io.movie.events.get(next), Some(&Event::Interrupt { .. })),
+    _ => false,
+  };
+  if is_interrupt { movie_io.next_event() } else { None }
+}
+
+/// Raises an interrupt of the machine, see `cpu::interrupt`. Recorded when
+/// recording and ignored when replaying, the movie supplies them then.
+pub fn raise_interrupt<M: Bus, P: Io>(cpu_state: &mut CpuState<M, MovieIo<P>>, instruction: [u8; 3]) {
+  match cpu_state.io.mode {
+    Mode::Replay { .. } if cpu_state.io.desync.is_none() => return,
+    Mode::Record => {
+      let cycles = cpu_state.io.cycles;
+      cpu_state.io.movie.events.push(Event::Interrupt { cycles, instruction });
+    },
+    _ => {},
+  }
+  interrupt(cpu_state, instruction);
+}
+
+#[test]
+fn movie_test() {
+  use bus::FlatMemory;
+  use cpu::{init_cpu_with, emulate, registers};
+
+  // a port whose reads change every time, like a player hammering a button
+  struct Noise(u8);
+  impl Io for Noise {
+    fn input(&mut self, _port: u8) -> u8 { self.0 = self.0.wrapping_mul(5).wrapping_add(3); self.0 }
+    fn output(&mut self, _port: u8, _value: u8) {}
+  }
+
+  // LXI SP,$2400; EI; loop: IN 1; ADD B; MOV B,A; JMP loop; at $0008: EI; RET
+  let program = [0x31, 0x00, 0x24, 0xfb, 0xdb, 0x01, 0x80, 0x47, 0xc3, 0x04, 0x00];
+  let run = |noise: u8, movie: Option<Movie>| {
+    let mut cpu_state = init_cpu_with(FlatMemory::new(), MovieIo::new(Noise(noise)));
+    cpu_state.memory[..program.len()].copy_from_slice(&program);
+    cpu_state.memory[0x08] = 0xfb;
+    cpu_state.memory[0x09] = 0xc9;
+    match movie {
+      Some(movie) => cpu_state.io.start_replay(movie),
+      None => cpu_state.io.start_recording(),
+    }
+    for step in 0..200 {
+      sync(&mut cpu_state);
+      if step % 37 == 0 {
+        raise_interrupt(&mut cpu_state, [0xcf, 0x00, 0x00]);
+      }
+      emulate(&mut cpu_state).unwrap();
+    }
+    let movie = cpu_state.io.take_movie();
+    (registers(&cpu_state), movie, cpu_state.io.desync())
+  };
+
+  let (recorded, movie, _) = run(1, None);
+  assert!(movie.events.iter().any(|event| matches!(*event, Event::Interrupt { .. })));
+
+  let mut file = Vec::new();
+  movie.save(&mut file).unwrap();
+  let loaded = Movie::load(&mut &file[..]).unwrap();
+  assert_eq!(loaded, movie);
+
+  // different devices, same run
+  let (replayed, _, desync) = run(77, Some(loaded));
+  assert_eq!(desync, None);
+  assert_eq!(replayed, recorded);
+
+  let mut wrong = movie.clone();
+  wrong.events.remove(3);
+  let (_, _, desync) = run(1, Some(wrong));
+  assert!(desync.is_some());
+}
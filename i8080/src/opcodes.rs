@@ -0,0 +1,420 @@
+//! What every 8080 opcode is: mnemonic, operands, size, timing and the flags it writes.
+//! The disassembler and the interpreter both take this table as the authority.
+
+/// Bytes following an opcode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+  /// Nothing, a 1 byte instruction
+  None,
+  /// 8 bit immediate
+  Byte,
+  /// 16 bit immediate
+  Word,
+  /// 16 bit memory address or jump target
+  Address,
+  /// 8 bit port number of IN and OUT
+  Port,
+}
+
+impl Operand {
+  /// Size in bytes of an instruction with this operand, opcode included
+  pub const fn instruction_size(self) -> u8 {
+    match self {
+      Operand::None => 1,
+      Operand::Byte | Operand::Port => 2,
+      Operand::Word | Operand::Address => 3,
+    }
+  }
+}
+
+/// Flag bits as PUSH PSW stores them, combined in `OpcodeInfo::flags`
+pub mod flag {
+  pub const S: u8 = 0x80;
+  pub const Z: u8 = 0x40;
+  pub const AC: u8 = 0x10;
+  pub const P: u8 = 0x04;
+  pub const CY: u8 = 0x01;
+  pub const ALL: u8 = S | Z | AC | P | CY;
+  pub const NONE: u8 = 0x00;
+}
+
+/// Description of one opcode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpcodeInfo {
+  /// Mnemonic without operands, e.g. "MOV"
+  pub mnemonic: &'static str,
+  /// Register operands as written in assembly, e.g. "B, M", empty if there are none
+  pub registers: &'static str,
+  pub operand: Operand,
+  /// Size in bytes, opcode included
+  pub size: u8,
+  /// T-states; for conditional calls and returns when the condition is false
+  pub cycles: u8,
+  /// T-states of conditional calls and returns when the condition is true,
+  /// the same as `cycles` for every other instruction
+  pub cycles_taken: u8,
+  /// Flags the instruction writes, see `flag`
+  pub flags: u8,
+  /// One of the undocumented aliases of NOP, JMP, RET and CALL
+  pub undocumented: bool,
+}
+
+const fn op(mnemonic: &'static str, registers: &'static str, operand: Operand, cycles: u8, cycles_taken: u8, flags: u8) -> OpcodeInfo {
+  OpcodeInfo {
+    mnemonic,
+    registers,
+    operand,
+    size: operand.instruction_size(),
+    cycles,
+    cycles_taken,
+    flags,
+    undocumented: false,
+  }
+}
+
+const fn alias(mnemonic: &'static str, registers: &'static str, operand: Operand, cycles: u8, cycles_taken: u8, flags: u8) -> OpcodeInfo {
+  OpcodeInfo { undocumented: true, ..op(mnemonic, registers, operand, cycles, cycles_taken, flags) }
+}
+
+use self::flag::{S, Z, AC, P, CY, ALL, NONE};
+
+/// All 256 opcodes, indexed by opcode
+pub static OPCODES: [OpcodeInfo; 256] = [
+  op("NOP", "", Operand::None, 4, 4, NONE),              // 00
+  op("LXI", "B", Operand::Word, 10, 10, NONE),           // 01
+  op("STAX", "B", Operand::None, 7, 7, NONE),            // 02
+  op("INX", "B", Operand::None, 5, 5, NONE),             // 03
+  op("INR", "B", Operand::None, 5, 5, S | Z | AC | P),   // 04
+  op("DCR", "B", Operand::None, 5, 5, S | Z | AC | P),   // 05
+  op("MVI", "B", Operand::Byte, 7, 7, NONE),             // 06
+  op("RLC", "", Operand::None, 4, 4, CY),                // 07
+  alias("NOP", "", Operand::None, 4, 4, NONE),           // 08
+  op("DAD", "B", Operand::None, 10, 10, CY),             // 09
+  op("LDAX", "B", Operand::None, 7, 7, NONE),            // 0a
+  op("DCX", "B", Operand::None, 5, 5, NONE),             // 0b
+  op("INR", "C", Operand::None, 5, 5, S | Z | AC | P),   // 0c
+  op("DCR", "C", Operand::None, 5, 5, S | Z | AC | P),   // 0d
+  op("MVI", "C", Operand::Byte, 7, 7, NONE),             // 0e
+  op("RRC", "", Operand::None, 4, 4, CY),                // 0f
+  alias("NOP", "", Operand::None, 4, 4, NONE),           // 10
+  op("LXI", "D", Operand::Word, 10, 10, NONE),           // 11
+  op("STAX", "D", Operand::None, 7, 7, NONE),            // 12
+  op("INX", "D", Operand::None, 5, 5, NONE),             // 13
+  op("INR", "D", Operand::None, 5, 5, S | Z | AC | P),   // 14
+  op("DCR", "D", Operand::None, 5, 5, S | Z | AC | P),   // 15
+  op("MVI", "D", Operand::Byte, 7, 7, NONE),             // 16
+  op("RAL", "", Operand::None, 4, 4, CY),                // 17
+  alias("NOP", "", Operand::None, 4, 4, NONE),           // 18
+  op("DAD", "D", Operand::None, 10, 10, CY),             // 19
+  op("LDAX", "D", Operand::None, 7, 7, NONE),            // 1a
+  op("DCX", "D", Operand::None, 5, 5, NONE),             // 1b
+  op("INR", "E", Operand::None, 5, 5, S | Z | AC | P),   // 1c
+  op("DCR", "E", Operand::None, 5, 5, S | Z | AC | P),   // 1d
+  op("MVI", "E", Operand::Byte, 7, 7, NONE),             // 1e
+  op("RAR", "", Operand::None, 4, 4, CY),                // 1f
+  alias("NOP", "", Operand::None, 4, 4, NONE),           // 20
+  op("LXI", "H", Operand::Word, 10, 10, NONE),           // 21
+  op("SHLD", "", Operand::Address, 16, 16, NONE),        // 22
+  op("INX", "H", Operand::None, 5, 5, NONE),             // 23
+  op("INR", "H", Operand::None, 5, 5, S | Z | AC | P),   // 24
+  op("DCR", "H", Operand::None, 5, 5, S | Z | AC | P),   // 25
+  op("MVI", "H", Operand::Byte, 7, 7, NONE),             // 26
+  op("DAA", "", Operand::None, 4, 4, ALL),               // 27
+  alias("NOP", "", Operand::None, 4, 4, NONE),           // 28
+  op("DAD", "H", Operand::None, 10, 10, CY),             // 29
+  op("LHLD", "", Operand::Address, 16, 16, NONE),        // 2a
+  op("DCX", "H", Operand::None, 5, 5, NONE),             // 2b
+  op("INR", "L", Operand::None, 5, 5, S | Z | AC | P),   // 2c
+  op("DCR", "L", Operand::None, 5, 5, S | Z | AC | P),   // 2d
+  op("MVI", "L", Operand::Byte, 7, 7, NONE),             // 2e
+  op("CMA", "", Operand::None, 4, 4, NONE),              // 2f
+  alias("NOP", "", Operand::None, 4, 4, NONE),           // 30
+  op("LXI", "SP", Operand::Word, 10, 10, NONE),          // 31
+  op("STA", "", Operand::Address, 13, 13, NONE),         // 32
+  op("INX", "SP", Operand::None, 5, 5, NONE),            // 33
+  op("INR", "M", Operand::None, 10, 10, S | Z | AC | P), // 34
+  op("DCR", "M", Operand::None, 10, 10, S | Z | AC | P), // 35
+  op("MVI", "M", Operand::Byte, 10, 10, NONE),           // 36
+  op("STC", "", Operand::None, 4, 4, CY),                // 37
+  alias("NOP", "", Operand::None, 4, 4, NONE),           // 38
+  op("DAD", "SP", Operand::None, 10, 10, CY),            // 39
+  op("LDA", "", Operand::Address, 13, 13, NONE),         // 3a
+  op("DCX", "SP", Operand::None, 5, 5, NONE),            // 3b
+  op("INR", "A", Operand::None, 5, 5, S | Z | AC | P),   // 3c
+  op("DCR", "A", Operand::None, 5, 5, S | Z | AC | P),   // 3d
+  op("MVI", "A", Operand::Byte, 7, 7, NONE),             // 3e
+  op("CMC", "", Operand::None, 4, 4, CY),                // 3f
+  op("MOV", "B, B", Operand::None, 5, 5, NONE),          // 40
+  op("MOV", "B, C", Operand::None, 5, 5, NONE),          // 41
+  op("MOV", "B, D", Operand::None, 5, 5, NONE),          // 42
+  op("MOV", "B, E", Operand::None, 5, 5, NONE),          // 43
+  op("MOV", "B, H", Operand::None, 5, 5, NONE),          // 44
+  op("MOV", "B, L", Operand::None, 5, 5, NONE),          // 45
+  op("MOV", "B, M", Operand::None, 7, 7, NONE),          // 46
+  op("MOV", "B, A", Operand::None, 5, 5, NONE),          // 47
+  op("MOV", "C, B", Operand::None, 5, 5, NONE),          // 48
+  op("MOV", "C, C", Operand::None, 5, 5, NONE),          // 49
+  op("MOV", "C, D", Operand::None, 5, 5, NONE),          // 4a
+  op("MOV", "C, E", Operand::None, 5, 5, NONE),          // 4b
+  op("MOV", "C, H", Operand::None, 5, 5, NONE),          // 4c
+  op("MOV", "C, L", Operand::None, 5, 5, NONE),          // 4d
+  op("MOV", "C, M", Operand::None, 7, 7, NONE),          // 4e
+  op("MOV", "C, A", Operand::None, 5, 5, NONE),          // 4f
+  op("MOV", "D, B", Operand::None, 5, 5, NONE),          // 50
+  op("MOV", "D, C", Operand::None, 5, 5, NONE),          // 51
+  op("MOV", "D, D", Operand::None, 5, 5, NONE),          // 52
+  op("MOV", "D, E", Operand::None, 5, 5, NONE),          // 53
+  op("MOV", "D, H", Operand::None, 5, 5, NONE),          // 54
+  op("MOV", "D, L", Operand::None, 5, 5, NONE),          // 55
+  op("MOV", "D, M", Operand::None, 7, 7, NONE),          // 56
+  op("MOV", "D, A", Operand::None, 5, 5, NONE),          // 57
+  op("MOV", "E, B", Operand::None, 5, 5, NONE),          // 58
+  op("MOV", "E, C", Operand::None, 5, 5, NONE),          // 59
+  op("MOV", "E, D", Operand::None, 5, 5, NONE),          // 5a
+  op("MOV", "E, E", Operand::None, 5, 5, NONE),          // 5b
+  op("MOV", "E, H", Operand::None, 5, 5, NONE),          // 5c
+  op("MOV", "E, L", Operand::None, 5, 5, NONE),          // 5d
+  op("MOV", "E, M", Operand::None, 7, 7, NONE),          // 5e
+  op("MOV", "E, A", Operand::None, 5, 5, NONE),          // 5f
+  op("MOV", "H, B", Operand::None, 5, 5, NONE),          // 60
+  op("MOV", "H, C", Operand::None, 5, 5, NONE),          // 61
+  op("MOV", "H, D", Operand::None, 5, 5, NONE),          // 62
+  op("MOV", "H, E", Operand::None, 5, 5, NONE),          // 63
+  op("MOV", "H, H", Operand::None, 5, 5, NONE),          // 64
+  op("MOV", "H, L", Operand::None, 5, 5, NONE),          // 65
+  op("MOV", "H, M", Operand::None, 7, 7, NONE),          // 66
+  op("MOV", "H, A", Operand::None, 5, 5, NONE),          // 67
+  op("MOV", "L, B", Operand::None, 5, 5, NONE),          // 68
+  op("MOV", "L, C", Operand::None, 5, 5, NONE),          // 69
+  op("MOV", "L, D", Operand::None, 5, 5, NONE),          // 6a
+  op("MOV", "L, E", Operand::None, 5, 5, NONE),          // 6b
+  op("MOV", "L, H", Operand::None, 5, 5, NONE),          // 6c
+  op("MOV", "L, L", Operand::None, 5, 5, NONE),          // 6d
+  op("MOV", "L, M", Operand::None, 7, 7, NONE),          // 6e
+  op("MOV", "L, A", Operand::None, 5, 5, NONE),          // 6f
+  op("MOV", "M, B", Operand::None, 7, 7, NONE),          // 70
+  op("MOV", "M, C", Operand::None, 7, 7, NONE),          // 71
+  op("MOV", "M, D", Operand::None, 7, 7, NONE),          // 72
+  op("MOV", "M, E", Operand::None, 7, 7, NONE),          // 73
+  op("MOV", "M, H", Operand::None, 7, 7, NONE),          // 74
+  op("MOV", "M, L", Operand::None, 7, 7, NONE),          // 75
+  op("HLT", "", Operand::None, 7, 7, NONE),              // 76
+  op("MOV", "M, A", Operand::None, 7, 7, NONE),          // 77
+  op("MOV", "A, B", Operand::None, 5, 5, NONE),          // 78
+  op("MOV", "A, C", Operand::None, 5, 5, NONE),          // 79
+  op("MOV", "A, D", Operand::None, 5, 5, NONE),          // 7a
+  op("MOV", "A, E", Operand::None, 5, 5, NONE),          // 7b
+  op("MOV", "A, H", Operand::None, 5, 5, NONE),          // 7c
+  op("MOV", "A, L", Operand::None, 5, 5, NONE),          // 7d
+  op("MOV", "A, M", Operand::None, 7, 7, NONE),          // 7e
+  op("MOV", "A, A", Operand::None, 5, 5, NONE),          // 7f
+  op("ADD", "B", Operand::None, 4, 4, ALL),              // 80
+  op("ADD", "C", Operand::None, 4, 4, ALL),              // 81
+  op("ADD", "D", Operand::None, 4, 4, ALL),              // 82
+  op("ADD", "E", Operand::None, 4, 4, ALL),              // 83
+  op("ADD", "H", Operand::None, 4, 4, ALL),              // 84
+  op("ADD", "L", Operand::None, 4, 4, ALL),              // 85
+  op("ADD", "M", Operand::None, 7, 7, ALL),              // 86
+  op("ADD", "A", Operand::None, 4, 4, ALL),              // 87
+  op("ADC", "B", Operand::None, 4, 4, ALL),              // 88
+  op("ADC", "C", Operand::None, 4, 4, ALL),              // 89
+  op("ADC", "D", Operand::None, 4, 4, ALL),              // 8a
+  op("ADC", "E", Operand::None, 4, 4, ALL),              // 8b
+  op("ADC", "H", Operand::None, 4, 4, ALL),              // 8c
+  op("ADC", "L", Operand::None, 4, 4, ALL),              // 8d
+  op("ADC", "M", Operand::None, 7, 7, ALL),              // 8e
+  op("ADC", "A", Operand::None, 4, 4, ALL),              // 8f
+  op("SUB", "B", Operand::None, 4, 4, ALL),              // 90
+  op("SUB", "C", Operand::None, 4, 4, ALL),              // 91
+  op("SUB", "D", Operand::None, 4, 4, ALL),              // 92
+  op("SUB", "E", Operand::None, 4, 4, ALL),              // 93
+  op("SUB", "H", Operand::None, 4, 4, ALL),              // 94
+  op("SUB", "L", Operand::None, 4, 4, ALL),              // 95
+  op("SUB", "M", Operand::None, 7, 7, ALL),              // 96
+  op("SUB", "A", Operand::None, 4, 4, ALL),              // 97
+  op("SBB", "B", Operand::None, 4, 4, ALL),              // 98
+  op("SBB", "C", Operand::None, 4, 4, ALL),              // 99
+  op("SBB", "D", Operand::None, 4, 4, ALL),              // 9a
+  op("SBB", "E", Operand::None, 4, 4, ALL),              // 9b
+  op("SBB", "H", Operand::None, 4, 4, ALL),              // 9c
+  op("SBB", "L", Operand::None, 4, 4, ALL),              // 9d
+  op("SBB", "M", Operand::None, 7, 7, ALL),              // 9e
+  op("SBB", "A", Operand::None, 4, 4, ALL),              // 9f
+  op("ANA", "B", Operand::None, 4, 4, ALL),              // a0
+  op("ANA", "C", Operand::None, 4, 4, ALL),              // a1
+  op("ANA", "D", Operand::None, 4, 4, ALL),              // a2
+  op("ANA", "E", Operand::None, 4, 4, ALL),              // a3
+  op("ANA", "H", Operand::None, 4, 4, ALL),              // a4
+  op("ANA", "L", Operand::None, 4, 4, ALL),              // a5
+  op("ANA", "M", Operand::None, 7, 7, ALL),              // a6
+  op("ANA", "A", Operand::None, 4, 4, ALL),              // a7
+  op("XRA", "B", Operand::None, 4, 4, ALL),              // a8
+  op("XRA", "C", Operand::None, 4, 4, ALL),              // a9
+  op("XRA", "D", Operand::None, 4, 4, ALL),              // aa
+  op("XRA", "E", Operand::None, 4, 4, ALL),              // ab
+  op("XRA", "H", Operand::None, 4, 4, ALL),              // ac
+  op("XRA", "L", Operand::None, 4, 4, ALL),              // ad
+  op("XRA", "M", Operand::None, 7, 7, ALL),              // ae
+  op("XRA", "A", Operand::None, 4, 4, ALL),              // af
+  op("ORA", "B", Operand::None, 4, 4, ALL),              // b0
+  op("ORA", "C", Operand::None, 4, 4, ALL),              // b1
+  op("ORA", "D", Operand::None, 4, 4, ALL),              // b2
+  op("ORA", "E", Operand::None, 4, 4, ALL),              // b3
+  op("ORA", "H", Operand::None, 4, 4, ALL),              // b4
+  op("ORA", "L", Operand::None, 4, 4, ALL),              // b5
+  op("ORA", "M", Operand::None, 7, 7, ALL),              // b6
+  op("ORA", "A", Operand::None, 4, 4, ALL),              // b7
+  op("CMP", "B", Operand::None, 4, 4, ALL),              // b8
+  op("CMP", "C", Operand::None, 4, 4, ALL),              // b9
+  op("CMP", "D", Operand::None, 4, 4, ALL),              // ba
+  op("CMP", "E", Operand::None, 4, 4, ALL),              // bb
+  op("CMP", "H", Operand::None, 4, 4, ALL),              // bc
+  op("CMP", "L", Operand::None, 4, 4, ALL),              // bd
+  op("CMP", "M", Operand::None, 7, 7, ALL),              // be
+  op("CMP", "A", Operand::None, 4, 4, ALL),              // bf
+  op("RNZ", "", Operand::None, 5, 11, NONE),             // c0
+  op("POP", "B", Operand::None, 10, 10, NONE),           // c1
+  op("JNZ", "", Operand::Address, 10, 10, NONE),         // c2
+  op("JMP", "", Operand::Address, 10, 10, NONE),         // c3
+  op("CNZ", "", Operand::Address, 11, 17, NONE),         // c4
+  op("PUSH", "B", Operand::None, 11, 11, NONE),          // c5
+  op("ADI", "", Operand::Byte, 7, 7, ALL),               // c6
+  op("RST", "0", Operand::None, 11, 11, NONE),           // c7
+  op("RZ", "", Operand::None, 5, 11, NONE),              // c8
+  op("RET", "", Operand::None, 10, 10, NONE),            // c9
+  op("JZ", "", Operand::Address, 10, 10, NONE),          // ca
+  alias("JMP", "", Operand::Address, 10, 10, NONE),      // cb
+  op("CZ", "", Operand::Address, 11, 17, NONE),          // cc
+  op("CALL", "", Operand::Address, 17, 17, NONE),        // cd
+  op("ACI", "", Operand::Byte, 7, 7, ALL),               // ce
+  op("RST", "1", Operand::None, 11, 11, NONE),           // cf
+  op("RNC", "", Operand::None, 5, 11, NONE),             // d0
+  op("POP", "D", Operand::None, 10, 10, NONE),           // d1
+  op("JNC", "", Operand::Address, 10, 10, NONE),         // d2
+  op("OUT", "", Operand::Port, 10, 10, NONE),            // d3
+  op("CNC", "", Operand::Address, 11, 17, NONE),         // d4
+  op("PUSH", "D", Operand::None, 11, 11, NONE),          // d5
+  op("SUI", "", Operand::Byte, 7, 7, ALL),               // d6
+  op("RST", "2", Operand::None, 11, 11, NONE),           // d7
+  op("RC", "", Operand::None, 5, 11, NONE),              // d8
+  alias("RET", "", Operand::None, 10, 10, NONE),         // d9
+  op("JC", "", Operand::Address, 10, 10, NONE),          // da
+  op("IN", "", Operand::Port, 10, 10, NONE),             // db
+  op("CC", "", Operand::Address, 11, 17, NONE),          // dc
+  alias("CALL", "", Operand::Address, 17, 17, NONE),     // dd
+  op("SBI", "", Operand::Byte, 7, 7, ALL),               // de
+  op("RST", "3", Operand::None, 11, 11, NONE),           // df
+  op("RPO", "", Operand::None, 5, 11, NONE),             // e0
+  op("POP", "H", Operand::None, 10, 10, NONE),           // e1
+  op("JPO", "", Operand::Address, 10, 10, NONE),         // e2
+  op("XTHL", "", Operand::None, 18, 18, NONE),           // e3
+  op("CPO", "", Operand::Address, 11, 17, NONE),         // e4
+  op("PUSH", "H", Operand::None, 11, 11, NONE),          // e5
+  op("ANI", "", Operand::Byte, 7, 7, ALL),               // e6
+  op("RST", "4", Operand::None, 11, 11, NONE),           // e7
+  op("RPE", "", Operand::None, 5, 11, NONE),             // e8
+  op("PCHL", "", Operand::None, 5, 5, NONE),             // e9
+  op("JPE", "", Operand::Address, 10, 10, NONE),         // ea
+  op("XCHG", "", Operand::None, 4, 4, NONE),             // eb
+  op("CPE", "", Operand::Address, 11, 17, NONE),         // ec
+  alias("CALL", "", Operand::Address, 17, 17, NONE),     // ed
+  op("XRI", "", Operand::Byte, 7, 7, ALL),               // ee
+  op("RST", "5", Operand::None, 11, 11, NONE),           // ef
+  op("RP", "", Operand::None, 5, 11, NONE),              // f0
+  op("POP", "PSW", Operand::None, 10, 10, ALL),          // f1
+  op("JP", "", Operand::Address, 10, 10, NONE),          // f2
+  op("DI", "", Operand::None, 4, 4, NONE),               // f3
+  op("CP", "", Operand::Address, 11, 17, NONE),          // f4
+  op("PUSH", "PSW", Operand::None, 11, 11, NONE),        // f5
+  op("ORI", "", Operand::Byte, 7, 7, ALL),               // f6
+  op("RST", "6", Operand::None, 11, 11, NONE),           // f7
+  op("RM", "", Operand::None, 5, 11, NONE),              // f8
+  op("SPHL", "", Operand::None, 5, 5, NONE),             // f9
+  op("JM", "", Operand::Address, 10, 10, NONE),          // fa
+  op("EI", "", Operand::None, 4, 4, NONE),               // fb
+  op("CM", "", Operand::Address, 11, 17, NONE),          // fc
+  alias("CALL", "", Operand::Address, 17, 17, NONE),     // fd
+  op("CPI", "", Operand::Byte, 7, 7, ALL),               // fe
+  op("RST", "7", Operand::None, 11, 11, NONE),           // ff
+];
+
+#[test]
+fn opcodes_test() {
+  use cpu::{init_cpu, emulate};
+
+  // run every opcode with all flags clear and all flags set, so every
+  // condition is taken once. jump targets, the stack and HL all point at 0
+  for (opcode, info) in OPCODES.iter().enumerate() {
+    for &flags in [false, true].iter() {
+      let mut cpu_state = init_cpu();
+      cpu_state.pc = 0x0100;
+      cpu_state.sp = 0x0200;
+      cpu_state.memory[0x0100] = opcode as u8;
+      cpu_state.cc.z = flags;
+      cpu_state.cc.s = flags;
+      cpu_state.cc.p = flags;
+      cpu_state.cc.cy = flags;
+
+      let cycles = emulate(&mut cpu_state).unwrap();
+      let taken = cpu_state.pc != 0x0100 + info.size as u16;
+      let expected = if taken { info.cycles_taken } else { info.cycles };
+      assert_eq!(cycles, expected as u32, "T-states of {:02x} {}", opcode, info.mnemonic);
+      // everything but jumps, calls and returns must step over exactly its size
+      let branches = info.mnemonic.starts_with('J') || info.mnemonic.starts_with('C') && info.operand == Operand::Address
+        || info.mnemonic.starts_with('R') && info.operand == Operand::None && info.registers.is_empty() || info.mnemonic == "RST" || info.mnemonic == "PCHL";
+      assert!(branches || !taken, "size of {:02x} {}", opcode, info.mnemonic);
+    }
+  }
+}
+
+#[test]
+fn flags_test() {
+  use cpu::{ConditionCode, init_cpu, emulate};
+
+  let packed = |cc: ConditionCode| {
+    (if cc.s { S } else { NONE }) | (if cc.z { Z } else { NONE }) | (if cc.ac { AC } else { NONE })
+      | (if cc.p { P } else { NONE }) | (if cc.cy { CY } else { NONE })
+  };
+  // xorshift, so the registers, flags and memory vary the same way every run
+  let mut seed: u32 = 0x2545_f491;
+  let mut random = move || {
+    seed ^= seed << 13;
+    seed ^= seed >> 17;
+    seed ^= seed << 5;
+    seed
+  };
+
+  // the flags that change over many runs of an opcode must be the ones it lists
+  for (opcode, info) in OPCODES.iter().enumerate() {
+    let mut changed = NONE;
+    for _ in 0..256 {
+      let mut cpu_state = init_cpu();
+      let (registers, more) = (random(), random());
+      cpu_state.a = registers as u8;
+      cpu_state.b = (registers >> 8) as u8;
+      cpu_state.c = (registers >> 16) as u8;
+      cpu_state.d = (registers >> 24) as u8;
+      cpu_state.e = more as u8;
+      cpu_state.h = (more >> 8) as u8;
+      cpu_state.l = (more >> 16) as u8;
+      let flags = (more >> 24) as u8;
+      cpu_state.cc = ConditionCode { s: flags & S != 0, z: flags & Z != 0, ac: flags & AC != 0, p: flags & P != 0, cy: flags & CY != 0 };
+      cpu_state.pc = 0x0100;
+      cpu_state.sp = 0x0200;
+      let hl = (cpu_state.h as usize) << 8 | cpu_state.l as usize;
+      let memory = random();
+      cpu_state.memory[hl] = memory as u8;
+      cpu_state.memory[0x0200] = (memory >> 8) as u8;
+      cpu_state.memory[0x0201] = (memory >> 16) as u8;
+      cpu_state.memory[0x0100] = opcode as u8;
+      cpu_state.memory[0x0101] = (memory >> 24) as u8;
+
+      let before = packed(cpu_state.cc);
+      emulate(&mut cpu_state).unwrap();
+      changed |= before ^ packed(cpu_state.cc);
+    }
+    // SBB A borrows exactly when the carry was set, so it writes CY back unchanged
+    let unchanged = if opcode == 0x9f { CY } else { NONE };
+    assert_eq!(changed | unchanged, info.flags, "flags of {:02x} {}", opcode, info.mnemonic);
+  }
+}
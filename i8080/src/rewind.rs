@@ -0,0 +1,342 @@
+//! Rewind: step backwards through execution
+//!
+//! `Journal` wraps the bus and records the old value of every byte written.
+//! `Rewind` executes instructions through `emulate` and keeps, per instruction,
+//! the registers before it and the bytes it overwrote, in a ring buffer of a
+//! fixed number of instructions. Stepping back undoes the writes and restores
+//! the registers. Bank switches of the bus are undone too, other port devices
+//! are not rewound.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+
+use bus::{Bus, BusFault};
+use cpu::{CpuState, ConditionCode, emulate};
+use error::EmulateError;
+use io::Io;
+
+/// A `Bus` that records the previous value of every byte written through it
+pub struct Journal<M> {
+  inner: M,
+  writes: Vec<(u16, u8)>,
+  /// bank selection as of the last `take_bank_switch`
+  banks: Vec<u8>,
+  /// fault latched before the journal read the old value of a write
+  fault: Option<BusFault>,
+}
+
+impl<M: Bus> Journal<M> {
+  pub fn new(inner: M) -> Journal<M> {
+    let banks = inner.banks();
+    Journal { inner, writes: Vec::new(), banks, fault: None }
+  }
+
+  /// The wrapped bus. Writes through it are not journaled, use it to load
+  /// programs before recording starts.
+  pub fn inner_mut(&mut self) -> &mut M {
+    &mut self.inner
+  }
+
+  /// Takes the (address, previous value) pairs written since the last call, oldest first
+  pub fn take_writes(&mut self) -> Vec<(u16, u8)> {
+    ::std::mem::take(&mut self.writes)
+  }
+
+  /// The bank selection before the banks were switched, if they were since
+  /// the last call. Switches come through port devices, so the journal only
+  /// sees them by comparing.
+  pub fn take_bank_switch(&mut self) -> Option<Vec<u8>> {
+    let banks = self.inner.banks();
+    if banks == self.banks { None } else { Some(::std::mem::replace(&mut self.banks, banks)) }
+  }
+}
+
+impl<M: Bus> Bus for Journal<M> {
+  fn read(&self, address: u16) -> u8 {
+    self.inner.read(address)
+  }
+
+  fn write(&mut self, address: u16, value: u8) {
+    // reading the old value must not latch a fault of its own, e.g. for unmapped addresses
+    let fault = self.inner.take_fault();
+    self.fault = self.fault.or(fault);
+    let previous = self.inner.read(address);
+    self.inner.take_fault();
+
+    self.writes.push((address, previous));
+    self.inner.write(address, value);
+  }
+
+  fn banks(&self) -> Vec<u8> {
+    self.inner.banks()
+  }
+
+  fn set_banks(&mut self, banks: &[u8]) {
+    self.inner.set_banks(banks);
+  }
+
+  fn take_fault(&mut self) -> Option<BusFault> {
+    let inner_fault = self.inner.take_fault();
+    self.fault.take().or(inner_fault)
+  }
+
+  fn save_state(&self, writer: &mut dyn Write) -> io::Result<()> {
+    self.inner.save_state(writer)
+  }
+
+  fn load_state(&mut self, reader: &mut dyn Read) -> io::Result<()> {
+    self.inner.load_state(reader)
+  }
+}
+
+impl<M> Deref for Journal<M> {
+  type Target = M;
+
+  fn deref(&self) -> &M {
+    &self.inner
+  }
+}
+
+/// Everything `emulate` may change besides memory and devices
+#[derive(Clone, Copy)]
+struct Snapshot {
+  a: u8,
+  b: u8,
+  c: u8,
+  d: u8,
+  e: u8,
+  h: u8,
+  l: u8,
+  sp: u16,
+  pc: u16,
+  cc: ConditionCode,
+  int_enable: u8,
+  int_delay: bool,
+  interrupt_request: Option<[u8; 3]>,
+  halted: bool,
+  cycles: u64,
+}
+
+/// One executed instruction: the state before it, the bytes it overwrote and
+/// the banks selected before it if it switched them
+struct Step {
+  before: Snapshot,
+  writes: Vec<(u16, u8)>,
+  banks: Option<Vec<u8>>,
+  /// the last instruction of a frame, see `Rewind::mark_frame`
+  frame_end: bool,
+}
+
+/// Ring buffer of the last executed instructions, see the module docs
+pub struct Rewind {
+  steps: VecDeque<Step>,
+  capacity: usize,
+}
+
+impl Rewind {
+  /// Remembers up to `capacity` instructions, the oldest are dropped first
+  pub fn new(capacity: usize) -> Rewind {
+    Rewind { steps: VecDeque::new(), capacity }
+  }
+
+  /// Number of instructions that can be stepped back
+  pub fn len(&self) -> usize {
+    self.steps.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.steps.is_empty()
+  }
+
+  /// Forgets the history, e.g. after loading a save state
+  pub fn clear(&mut self) {
+    self.steps.clear();
+  }
+
+  /// Executes one instruction with `emulate` and records it. Instructions
+  /// that fail are recorded too, they may have changed state before failing.
+  pub fn step<M: Bus, P: Io>(&mut self, cpu_state: &mut CpuState<Journal<M>, P>) -> Result<u32, EmulateError> {
+    let before = snapshot(cpu_state);
+    cpu_state.memory.take_writes();
+    cpu_state.memory.take_bank_switch();
+    let result = emulate(cpu_state);
+    let writes = cpu_state.memory.take_writes();
+    let banks = cpu_state.memory.take_bank_switch();
+
+    if self.capacity > 0 {
+      if self.steps.len() == self.capacity {
+        self.steps.pop_front();
+      }
+      self.steps.push_back(Step { before, writes, banks, frame_end: false });
+    }
+    result
+  }
+
+  /// Marks the last recorded instruction as the end of a frame, for `rewind_frames`
+  pub fn mark_frame(&mut self) {
+    if let Some(step) = self.steps.back_mut() {
+      step.frame_end = true;
+    }
+  }
+
+  /// Undoes the last recorded instruction, false if there is none left
+  pub fn step_back<M: Bus, P: Io>(&mut self, cpu_state: &mut CpuState<Journal<M>, P>) -> bool {
+    match self.steps.pop_back() {
+      Some(step) => {
+        for &(address, value) in step.writes.iter().rev() {
+          cpu_state.memory.inner.write(address, value);
+        }
+        if let Some(banks) = step.banks {
+          cpu_state.memory.inner.set_banks(&banks);
+          cpu_state.memory.banks = banks;
+        }
+        restore(cpu_state, &step.before);
+        true
+      },
+      None => false,
+    }
+  }
+
+  /// Steps back to the start of the frame `frames` frames ago; a frame in
+  /// progress counts as one. Returns the number of instructions undone.
+  pub fn rewind_frames<M: Bus, P: Io>(&mut self, cpu_state: &mut CpuState<Journal<M>, P>, frames: usize) -> usize {
+    let mut undone = 0;
+    let mut frames_left = frames;
+
+    // a frame in progress, i.e. instructions behind the last mark, counts as one
+    let in_progress = self.steps.back().is_some_and(|step| !step.frame_end);
+    if in_progress && frames_left > 0 {
+      while self.steps.back().is_some_and(|step| !step.frame_end) {
+        self.step_back(cpu_state);
+        undone += 1;
+      }
+      frames_left -= 1;
+    }
+
+    for _ in 0..frames_left {
+      if self.steps.is_empty() {
+        break;
+      }
+      // the marked last instruction of the frame, then the rest of it
+      self.step_back(cpu_state);
+      undone += 1;
+      while self.steps.back().is_some_and(|step| !step.frame_end) {
+        self.step_back(cpu_state);
+        undone += 1;
+      }
+    }
+    undone
+  }
+}
+
+fn snapshot<M: Bus, P: Io>(cpu_state: &CpuState<M, P>) -> Snapshot {
+  Snapshot {
+    a: cpu_state.a,
+    b: cpu_state.b,
+    c: cpu_state.c,
+    d: cpu_state.d,
+    e: cpu_state.e,
+    h: cpu_state.h,
+    l: cpu_state.l,
+    sp: cpu_state.sp,
+    pc: cpu_state.pc,
+    cc: cpu_state.cc,
+    int_enable: cpu_state.int_enable,
+    int_delay: cpu_state.int_delay,
+    interrupt_request: cpu_state.interrupt_request,
+    halted: cpu_state.halted,
+    cycles: cpu_state.cycles,
+  }
+}
+
+fn restore<M: Bus, P: Io>(cpu_state: &mut CpuState<M, P>, snapshot: &Snapshot) {
+  cpu_state.a = snapshot.a;
+  cpu_state.b = snapshot.b;
+  cpu_state.c = snapshot.c;
+  cpu_state.d = snapshot.d;
+  cpu_state.e = snapshot.e;
+  cpu_state.h = snapshot.h;
+  cpu_state.l = snapshot.l;
+  cpu_state.sp = snapshot.sp;
+  cpu_state.pc = snapshot.pc;
+  cpu_state.cc = snapshot.cc;
+  cpu_state.int_enable = snapshot.int_enable;
+  cpu_state.int_delay = snapshot.int_delay;
+  cpu_state.interrupt_request = snapshot.interrupt_request;
+  cpu_state.halted = snapshot.halted;
+  cpu_state.cycles = snapshot.cycles;
+}
+
+#[test]
+fn rewind_test() {
+  use bus::FlatMemory;
+  use cpu::{init_cpu_with, registers};
+  use io::NullIo;
+
+  // LXI SP,$2400; MVI A,$01; loop: STA $2000; PUSH PSW; INR A; JMP loop
+  let program = [0x31, 0x00, 0x24, 0x3e, 0x01, 0x32, 0x00, 0x20, 0xf5, 0x3c, 0xc3, 0x05, 0x00];
+  let mut cpu_state = init_cpu_with(Journal::new(FlatMemory::new()), NullIo);
+  cpu_state.memory.inner_mut()[..program.len()].copy_from_slice(&program);
+
+  let mut rewind = Rewind::new(100);
+  let mut history = Vec::new();
+  for _ in 0..10 {
+    history.push((registers(&cpu_state), cpu_state.memory[0x2000], cpu_state.memory[0x23fe]));
+    rewind.step(&mut cpu_state).unwrap();
+  }
+  assert_eq!(rewind.len(), 10);
+
+  // walk back one instruction at a time and meet every earlier state again
+  while let Some((registers_before, stored, pushed)) = history.pop() {
+    assert!(rewind.step_back(&mut cpu_state));
+    assert_eq!(registers(&cpu_state), registers_before);
+    assert_eq!(cpu_state.memory[0x2000], stored);
+    assert_eq!(cpu_state.memory[0x23fe], pushed);
+  }
+  assert!(!rewind.step_back(&mut cpu_state));
+  assert!(cpu_state.memory[0x2000..].iter().all(|&byte| byte == 0));
+
+  // frames of 3 instructions, then one in progress
+  for step in 0..11 {
+    rewind.step(&mut cpu_state).unwrap();
+    if step % 3 == 2 {
+      rewind.mark_frame();
+    }
+  }
+  assert_eq!(rewind.rewind_frames(&mut cpu_state, 2), 5);
+  assert_eq!(rewind.len(), 6);
+  assert_eq!(rewind.rewind_frames(&mut cpu_state, 5), 6);
+  assert_eq!(cpu_state.pc, 0x0000);
+
+  // bank switches are undone with the instructions that made them
+  use memory_map::{BankLatch, BankRegister, MemoryMap};
+  let register = BankRegister::new();
+  let mut memory = MemoryMap::new();
+  memory.map_banked("banked", 0x0000, 0x7fff, 2, register.clone());
+  memory.map_ram("common", 0x8000, 0xffff);
+  // MVI A,1; OUT $40; STA $0010; XRA A; OUT $40
+  memory.load(0x8000, &[0x3e, 0x01, 0xd3, 0x40, 0x32, 0x10, 0x00, 0xaf, 0xd3, 0x40]);
+  let mut banked = init_cpu_with(Journal::new(memory), BankLatch::new(NullIo, 0x40, register.clone()));
+  banked.pc = 0x8000;
+  let mut rewind = Rewind::new(10);
+  for _ in 0..5 {
+    rewind.step(&mut banked).unwrap();
+  }
+  assert_eq!(banked.memory.active_bank(0x0010), Some(0));
+  rewind.step_back(&mut banked);
+  rewind.step_back(&mut banked);
+  assert_eq!(register.get(), 1);
+  assert_eq!(banked.memory.read(0x0010), 0x01);
+  rewind.step_back(&mut banked);
+  assert_eq!(banked.memory.read(0x0010), 0x00);
+  rewind.step_back(&mut banked);
+  assert_eq!(register.get(), 0);
+
+  // the ring buffer drops the oldest instructions
+  let mut rewind = Rewind::new(4);
+  for _ in 0..10 {
+    rewind.step(&mut cpu_state).unwrap();
+  }
+  assert_eq!(rewind.len(), 4);
+}
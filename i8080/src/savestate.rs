@@ -0,0 +1,165 @@
+//! Save states: a snapshot of the processor, memory and devices in a versioned binary file
+//!
+//! The file starts with `MAGIC` and the format `VERSION`, followed by the registers,
+//! flags and interrupt state, then whatever `Bus::save_state` and `Io::save_state`
+//! write. Numbers are little endian.
+
+use std::io::{self, Read, Write};
+
+use bus::Bus;
+use cpu::CpuState;
+use io::Io;
+
+/// First bytes of every save state
+pub const MAGIC: &[u8; 8] = b"I8080SAV";
+
+/// Format version, bumped whenever the layout changes
+pub const VERSION: u16 = 1;
+
+/// Writes `cpu_state` with its memory and devices to `writer`
+pub fn save_state<M: Bus, P: Io, W: Write>(cpu_state: &CpuState<M, P>, writer: &mut W) -> io::Result<()> {
+  writer.write_all(MAGIC)?;
+  write_u16(writer, VERSION)?;
+
+  writer.write_all(&[cpu_state.a, cpu_state.b, cpu_state.c, cpu_state.d, cpu_state.e, cpu_state.h, cpu_state.l])?;
+  write_u16(writer, cpu_state.sp)?;
+  write_u16(writer, cpu_state.pc)?;
+  let cc = &cpu_state.cc;
+  writer.write_all(&[cc.z as u8, cc.s as u8, cc.p as u8, cc.cy as u8, cc.ac as u8])?;
+  writer.write_all(&[cpu_state.int_enable, cpu_state.int_delay as u8, cpu_state.halted as u8, cpu_state.strict as u8])?;
+  match cpu_state.interrupt_request {
+    Some(instruction) => { writer.write_all(&[1])?; writer.write_all(&instruction)?; },
+    None => writer.write_all(&[0, 0, 0, 0])?,
+  }
+  write_u64(writer, cpu_state.cycles)?;
+
+  cpu_state.memory.save_state(writer)?;
+  cpu_state.io.save_state(writer)
+}
+
+/// Restores `cpu_state`, its memory and devices from a save state written by
+/// `save_state`. The machine must be built the same way it was when saving.
+/// On error `cpu_state` may be partly restored.
+pub fn load_state<M: Bus, P: Io, R: Read>(cpu_state: &mut CpuState<M, P>, reader: &mut R) -> io::Result<()> {
+  let mut magic = [0; 8];
+  reader.read_exact(&mut magic)?;
+  if &magic != MAGIC {
+    return Err(invalid_data("not a save state"));
+  }
+  let version = read_u16(reader)?;
+  if version != VERSION {
+    return Err(invalid_data(&format!("save state version {} is not supported, expected {}", version, VERSION)));
+  }
+
+  let mut registers = [0; 7];
+  reader.read_exact(&mut registers)?;
+  let sp = read_u16(reader)?;
+  let pc = read_u16(reader)?;
+  let mut flags = [0; 5];
+  reader.read_exact(&mut flags)?;
+  let mut interrupt_state = [0; 4];
+  reader.read_exact(&mut interrupt_state)?;
+  let mut interrupt_request = [0; 4];
+  reader.read_exact(&mut interrupt_request)?;
+  let cycles = read_u64(reader)?;
+
+  cpu_state.a = registers[0];
+  cpu_state.b = registers[1];
+  cpu_state.c = registers[2];
+  cpu_state.d = registers[3];
+  cpu_state.e = registers[4];
+  cpu_state.h = registers[5];
+  cpu_state.l = registers[6];
+  cpu_state.sp = sp;
+  cpu_state.pc = pc;
+  cpu_state.cc.z = flags[0] != 0;
+  cpu_state.cc.s = flags[1] != 0;
+  cpu_state.cc.p = flags[2] != 0;
+  cpu_state.cc.cy = flags[3] != 0;
+  cpu_state.cc.ac = flags[4] != 0;
+  cpu_state.int_enable = interrupt_state[0];
+  cpu_state.int_delay = interrupt_state[1] != 0;
+  cpu_state.halted = interrupt_state[2] != 0;
+  cpu_state.strict = interrupt_state[3] != 0;
+  cpu_state.interrupt_request = if interrupt_request[0] != 0 {
+    Some([interrupt_request[1], interrupt_request[2], interrupt_request[3]])
+  } else {
+    None
+  };
+  cpu_state.cycles = cycles;
+
+  cpu_state.memory.load_state(reader)?;
+  cpu_state.io.load_state(reader)
+}
+
+/// Writes `value` little endian, for `Bus` and `Io` implementations
+pub fn write_u16(writer: &mut dyn Write, value: u16) -> io::Result<()> {
+  writer.write_all(&value.to_le_bytes())
+}
+
+/// Writes `value` little endian, for `Bus` and `Io` implementations
+pub fn write_u64(writer: &mut dyn Write, value: u64) -> io::Result<()> {
+  writer.write_all(&value.to_le_bytes())
+}
+
+/// Reads a byte, for `Bus` and `Io` implementations
+pub fn read_u8(reader: &mut dyn Read) -> io::Result<u8> {
+  let mut bytes = [0; 1];
+  reader.read_exact(&mut bytes)?;
+  Ok(bytes[0])
+}
+
+/// Reads a little endian u16, for `Bus` and `Io` implementations
+pub fn read_u16(reader: &mut dyn Read) -> io::Result<u16> {
+  let mut bytes = [0; 2];
+  reader.read_exact(&mut bytes)?;
+  Ok(u16::from_le_bytes(bytes))
+}
+
+/// Reads a little endian u64, for `Bus` and `Io` implementations
+pub fn read_u64(reader: &mut dyn Read) -> io::Result<u64> {
+  let mut bytes = [0; 8];
+  reader.read_exact(&mut bytes)?;
+  Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[test]
+fn save_state_test() {
+  use cpu::{init_cpu, emulate, interrupt_rst, registers};
+
+  // LXI SP,$2400; MVI A,$42; STA $2000; EI; NOP; HLT
+  let program = [0x31, 0x00, 0x24, 0x3e, 0x42, 0x32, 0x00, 0x20, 0xfb, 0x00, 0x76];
+  let mut cpu_state = init_cpu();
+  cpu_state.memory[..program.len()].copy_from_slice(&program);
+  for _ in 0..4 {
+    emulate(&mut cpu_state).unwrap();
+  }
+  interrupt_rst(&mut cpu_state, 1);
+  cpu_state.cc.cy = true;
+
+  let mut file = Vec::new();
+  save_state(&cpu_state, &mut file).unwrap();
+
+  let mut restored = init_cpu();
+  load_state(&mut restored, &mut &file[..]).unwrap();
+  assert_eq!(registers(&restored), registers(&cpu_state));
+  assert_eq!(restored.int_delay, cpu_state.int_delay);
+  assert_eq!(restored.interrupt_request, cpu_state.interrupt_request);
+  assert_eq!(&restored.memory[..], &cpu_state.memory[..]);
+
+  // both continue the same way
+  for _ in 0..3 {
+    assert_eq!(emulate(&mut restored), emulate(&mut cpu_state));
+    assert_eq!(registers(&restored), registers(&cpu_state));
+  }
+
+  let mut bad_version = file.clone();
+  bad_version[8] = 0xff;
+  assert_eq!(load_state(&mut restored, &mut &bad_version[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+  assert_eq!(load_state(&mut restored, &mut &b"I8080"[..]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+  assert_eq!(load_state(&mut restored, &mut &file[..100]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,125 @@
+//! The classic 8080 exercisers run as CP/M programs: cpudiag, TST8080,
+//! 8080PRE and 8080EXM. Changes to `emulate` should keep them passing.
+//!
+//! The programs are not part of the repository, so these tests are ignored
+//! and only run with --ignored, even once the programs are there. Put them
+//! into tests/roms as cpudiag.bin, TST8080.COM, 8080PRE.COM and 8080EXM.COM,
+//! or point I8080_ROMS at a directory holding them; 8080EXM takes about 23
+//! billion T-states:
+//!
+//! ```text
+//! cargo test --release --test exercisers -- --ignored
+//! ```
+
+extern crate i8080;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use i8080::{BufferConsole, Cpm, init_cpu};
+
+/// Runs the .COM program `file` until it warm boots and returns what it printed
+fn run(file: &str, cycle_limit: u64) -> String {
+  let directory = env::var_os("I8080_ROMS").map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("roms"));
+  let path = directory.join(file);
+  let mut program = Vec::new();
+  if let Err(why) = File::open(&path).and_then(|mut file| file.read_to_end(&mut program)) {
+    panic!("could not read {}: {}; put the program there or point I8080_ROMS at it", path.display(), why);
+  }
+
+  let mut cpu_state = init_cpu();
+  let mut cpm = Cpm::new(BufferConsole::default());
+  cpm.load(&mut cpu_state, &program).unwrap();
+  while !cpm.exited() {
+    if let Err(error) = cpm.step(&mut cpu_state) {
+      panic!("{} stopped: {}\n{}", file, error, String::from_utf8_lossy(&cpm.console.output));
+    }
+    assert!(cpu_state.cycles < cycle_limit, "{} still running after {} T-states\n{}",
+      file, cycle_limit, String::from_utf8_lossy(&cpm.console.output));
+  }
+  if let Some(error) = cpm.error() {
+    panic!("{}: {}", file, error);
+  }
+
+  String::from_utf8_lossy(&cpm.console.output).replace('\r', "")
+}
+
+/// An instruction group of 8080EXM whose CRC came out wrong
+#[derive(Debug, PartialEq)]
+struct CrcMismatch {
+  group: String,
+  expected: String,
+  found: String,
+}
+
+/// The failed groups in 8080EXM output, lines like
+/// `aluop nn......................  ERROR **** crc expected:9e922f9e found:5a3d4b9b`
+fn crc_mismatches(output: &str) -> Vec<CrcMismatch> {
+  let field = |line: &str, name: &str| line.split(name).nth(1)
+    .and_then(|rest| rest.split_whitespace().next())
+    .unwrap_or("?").to_string();
+  output.lines().filter(|line| line.contains("ERROR")).map(|line| {
+    CrcMismatch {
+      group: line.split("..").next().unwrap().trim().to_string(),
+      expected: field(line, "expected:"),
+      found: field(line, "found:"),
+    }
+  }).collect()
+}
+
+/// Fails with one line per mismatched group
+fn assert_crcs(file: &str, output: &str) {
+  let mismatches = crc_mismatches(output);
+  let report = mismatches.iter()
+    .map(|mismatch| format!("  {}: crc {}, expected {}", mismatch.group, mismatch.found, mismatch.expected))
+    .collect::<Vec<_>>().join("\n");
+  assert!(mismatches.is_empty(), "{} failed {} instruction groups:\n{}", file, mismatches.len(), report);
+}
+
+#[test]
+#[ignore = "needs the program in tests/roms or I8080_ROMS, run with --ignored"]
+fn cpudiag() {
+  let output = run("cpudiag.bin", 10_000_000);
+  assert!(output.contains("CPU IS OPERATIONAL"), "cpudiag failed:\n{}", output);
+}
+
+#[test]
+#[ignore = "needs the program in tests/roms or I8080_ROMS, run with --ignored"]
+fn tst8080() {
+  let output = run("TST8080.COM", 10_000_000);
+  assert!(output.contains("CPU IS OPERATIONAL"), "TST8080 failed:\n{}", output);
+}
+
+#[test]
+#[ignore = "needs the program in tests/roms or I8080_ROMS, run with --ignored"]
+fn exerciser_8080pre() {
+  let output = run("8080PRE.COM", 100_000_000);
+  assert!(output.contains("complete") && !output.contains("ERROR"), "8080PRE failed:\n{}", output);
+}
+
+#[test]
+#[ignore = "needs the program in tests/roms or I8080_ROMS and a release build, run with --ignored"]
+fn exerciser_8080exm() {
+  let output = run("8080EXM.COM", 50_000_000_000);
+  assert_crcs("8080EXM", &output);
+  assert!(output.contains("Tests complete"), "8080EXM did not finish:\n{}", output);
+}
+
+#[test]
+fn crc_mismatches_test() {
+  let output = "\
+8080 instruction exerciser
+dad <b,d,h,sp>................  PASS! crc is:14474ba6
+aluop nn......................  ERROR **** crc expected:9e922f9e found:5a3d4b9b
+<daa,cma,stc,cmc>.............  ERROR **** crc expected:bb3f030c found:00000000
+Tests complete
+";
+  assert_eq!(crc_mismatches(output), vec![
+    CrcMismatch { group: "aluop nn".to_string(), expected: "9e922f9e".to_string(), found: "5a3d4b9b".to_string() },
+    CrcMismatch { group: "<daa,cma,stc,cmc>".to_string(), expected: "bb3f030c".to_string(), found: "00000000".to_string() },
+  ]);
+  assert!(crc_mismatches("dad <b,d,h,sp>................  PASS! crc is:14474ba6").is_empty());
+}